@@ -4,9 +4,18 @@ use specs::{Component, VecStorage};
 // -------------------------------------------------------------------
 
 /// Obstacles block the line of vision of enemies, so that the player
-/// can go undetected.
+/// can go undetected. See `vision::Vision` for the queries.
 #[derive(Debug, Component)]
 #[storage(VecStorage)]
 pub struct Obstacle {
     pub aabb: AABB<f32>,
 }
+
+/// Light sources make the player easier to spot by the enemies. The light is
+/// at full intensity on the source and fades until `radius`.
+#[derive(Debug, Clone, Copy, Component)]
+#[storage(VecStorage)]
+pub struct Light {
+    pub radius: f32,
+    pub intensity: f32,
+}
@@ -1,11 +1,53 @@
+use crate::{
+    event::EventConfig,
+    systems::{
+        aim::AimSource,
+        health::{Damage, DamageType},
+        BulletBehavior, ColliderObjectType,
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Angles are written in degrees in the configuration files, in the fields that end with
+/// `_deg`. They are converted to radians when the files are loaded so the game only uses
+/// radians.
+///
+/// Use it with `#[serde(rename = "angle_deg", with = "crate::config::degrees")]`.
+pub mod degrees {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(angle: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        angle.to_degrees().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        f32::deserialize(deserializer).map(f32::to_radians)
+    }
+}
 
 /// Load configuration from config file (ronronron)
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PlayerConfig {
     pub fall_rot_speed: f32,
     pub player_speed: f32,
     pub health: i32,
+
+    /// How long the player cannot be hurt after being hit, in seconds.
+    pub invulnerability: f32,
+
+    pub visibility: VisibilityConfig,
+    pub melee: MeleeConfig,
+    pub dash: DashConfig,
+    pub fall: FallConfig,
+
+    /// Input used to aim (mouse, gamepad...)
+    #[serde(default)]
+    pub aim_source: AimSource,
+
+    /// Damage multiplier per damage type.
+    #[serde(default)]
+    pub resistances: HashMap<DamageType, f32>,
 }
 
 impl Default for PlayerConfig {
@@ -13,7 +55,137 @@ impl Default for PlayerConfig {
         Self {
             player_speed: 50.0,
             health: 5,
+            invulnerability: 1.0,
             fall_rot_speed: 25.0,
+            visibility: VisibilityConfig::default(),
+            melee: MeleeConfig::default(),
+            dash: DashConfig::default(),
+            fall: FallConfig::default(),
+            aim_source: AimSource::default(),
+            resistances: HashMap::new(),
+        }
+    }
+}
+
+/// How easy it is for the enemies to see the player. The visibility score is between
+/// 0 (invisible) and 1.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct VisibilityConfig {
+    /// Visibility when the player is not moving in the dark.
+    pub ambient: f32,
+
+    /// How much the lights contribute to the visibility.
+    pub light_weight: f32,
+
+    /// How much moving at full speed contributes to the visibility.
+    pub movement_weight: f32,
+
+    /// Noise made when shooting. It is added to the visibility.
+    pub shoot_noise: f32,
+
+    /// How fast the noise fades, per second.
+    pub noise_decay: f32,
+}
+
+impl Default for VisibilityConfig {
+    fn default() -> Self {
+        Self {
+            ambient: 0.2,
+            light_weight: 0.6,
+            movement_weight: 0.3,
+            shoot_noise: 0.5,
+            noise_decay: 1.0,
+        }
+    }
+}
+
+/// What happens when the player falls from the walkable areas.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct FallConfig {
+    pub mode: FallMode,
+
+    /// How long the player can stay out of the walkable areas before falling, in seconds.
+    pub grace_time: f32,
+}
+
+impl Default for FallConfig {
+    fn default() -> Self {
+        Self {
+            mode: FallMode::GameOver,
+            grace_time: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub enum FallMode {
+    /// Falling ends the game.
+    GameOver,
+
+    /// The player loses some health and comes back at the last position where it was on
+    /// the ground.
+    Respawn { damage: i32 },
+}
+
+/// Dodge roll of the player. The player cannot be hit by bullets and does not fall while
+/// dashing.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct DashConfig {
+    /// Distance covered by the dash.
+    pub distance: f32,
+
+    /// How long the dash lasts, in seconds.
+    pub duration: f32,
+
+    /// Time between two dashes, in seconds.
+    pub cooldown: f32,
+}
+
+impl Default for DashConfig {
+    fn default() -> Self {
+        Self {
+            distance: 48.0,
+            duration: 0.25,
+            cooldown: 1.0,
+        }
+    }
+}
+
+/// Close combat attack of the player.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct MeleeConfig {
+    /// Distance between the player and the center of the hitbox.
+    pub range: f32,
+
+    /// Size of the square hitbox.
+    pub size: f32,
+
+    /// How long the hitbox stays, in seconds.
+    pub duration: f32,
+
+    /// Time between two attacks, in seconds.
+    pub cooldown: f32,
+
+    pub damage: Damage,
+
+    /// Noise made when hitting an enemy. Takedowns are silent.
+    pub noise: f32,
+
+    /// Angle in radians behind the enemy from which a takedown is possible.
+    #[serde(rename = "takedown_angle_deg", with = "degrees")]
+    pub takedown_angle: f32,
+}
+
+impl Default for MeleeConfig {
+    fn default() -> Self {
+        Self {
+            range: 12.0,
+            size: 16.0,
+            duration: 0.15,
+            cooldown: 0.5,
+            damage: Damage::default(),
+            noise: 0.2,
+            takedown_angle: 120.0f32.to_radians(),
         }
     }
 }
@@ -46,15 +218,156 @@ impl Default for CameraConfig {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct EnemyConfig {
-    pub simple_enemy: SimpleEnemyConfig,
-    pub creepy_boss: CreepyFirstBossConfig,
+    /// Enemies spawned by the waves and the alarm, chosen randomly (names in enemies.ron).
+    pub wave_enemies: Vec<String>,
+
+    /// Enemy spawned at the boss spawn location (name in enemies.ron).
+    pub boss: String,
+
+    pub alarm: AlarmConfig,
 }
 
+impl Default for EnemyConfig {
+    fn default() -> Self {
+        Self {
+            wave_enemies: vec![String::from("simple")],
+            boss: String::from("creepy_boss"),
+            alarm: AlarmConfig::default(),
+        }
+    }
+}
+
+/// How the alarm propagates between enemies.
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct AlarmConfig {
+    /// Enemies closer than that to the enemy that raises the alarm will be alerted.
+    pub radius: f32,
+
+    /// If true, only the enemies that can see the enemy that raises the alarm are
+    /// alerted.
+    pub line_of_sight: bool,
+
+    /// How much the alarm level increases every time the alarm is raised.
+    pub level_increase: f32,
+
+    /// How much the alarm level decreases every second.
+    pub cooldown: f32,
+
+    /// Reinforcements are called when the alarm level reaches that value.
+    pub max_level: f32,
+
+    /// Number of enemies spawned when the alarm level is at maximum.
+    pub reinforcements: i32,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            radius: 100.0,
+            line_of_sight: true,
+            level_increase: 1.0,
+            cooldown: 0.1,
+            max_level: 3.0,
+            reinforcements: 2,
+        }
+    }
+}
+
+// ---------------------------------------------------------
+
+/// All the enemies that can be spawned, by name. Loaded from assets/prefab/enemies.ron
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct EnemyArchetypeConfig {
+    pub enemies: HashMap<String, EnemyArchetype>,
+}
+
+impl EnemyArchetypeConfig {
+    /// Names of all the bullets fired directly by the enemies. Bullets of the patterns are
+    /// not included.
+    pub fn bullet_names(&self) -> Vec<&str> {
+        self.enemies
+            .values()
+            .filter_map(|archetype| match archetype.behavior {
+                EnemyBehavior::Simple(ref config) => Some(config.bullet.as_str()),
+                EnemyBehavior::CreepyFirstBoss { .. } => None,
+            })
+            .collect()
+    }
+}
+
+/// Description of a kind of enemy.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EnemyArchetype {
+    /// Name of the spritesheet in the texture folder.
+    pub sprite_sheet: String,
+
+    /// Animations by name. The enemy behaviors use `walk_down`, `walk_left`, `walk_right`,
+    /// `walk_up` and `shoot`. The enemy is not animated if there is none.
+    #[serde(default)]
+    pub animations: HashMap<String, AnimationConfig>,
+
+    /// Size of the square collider.
+    pub collider_size: f32,
+
+    pub health: i32,
+
+    /// How long the enemy cannot be hurt after being hit, in seconds.
+    #[serde(default)]
+    pub invulnerability: f32,
+
+    /// Damage multiplier per damage type.
+    #[serde(default)]
+    pub resistances: HashMap<DamageType, f32>,
+
+    /// Grounded enemies can be pushed around and fall into the pits.
+    #[serde(default)]
+    pub grounded: bool,
+
+    pub behavior: EnemyBehavior,
+
+    /// Pickups that the enemy can leave when it dies.
+    #[serde(default)]
+    pub drops: Vec<DropConfig>,
+}
+
+/// What the enemy does.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum EnemyBehavior {
+    /// Guard its position until it sees the player, then follow and shoot.
+    Simple(SimpleEnemyConfig),
+
+    /// Stay in place and fire a bullet pattern (name in patterns.ron).
+    CreepyFirstBoss { pattern: String },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnimationConfig {
+    pub sprite_indexes: Vec<usize>,
+
+    /// Time between two sprites, in seconds.
+    pub step_duration: f32,
+}
+
+/// Weapon pickup left by a dead enemy.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DropConfig {
+    /// Name of the weapon in weapons.ron
+    pub weapon: String,
+
+    /// Probability that the pickup is dropped, between 0 and 1.
+    pub chance: f32,
+
+    /// Name of the spritesheet in the texture folder.
+    pub sprite_sheet: String,
+    pub sprite_number: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct SimpleEnemyConfig {
-    pub bullet_speed: f32,
+    /// Name of the bullet (in bullets.ron)
+    pub bullet: String,
 
     /// How long does the enemy walk.
     pub walk_duration: f32,
@@ -62,61 +375,397 @@ pub struct SimpleEnemyConfig {
     /// Shoot duration. How long does the enemy shoot? It is related to the shooting animation.
     pub shoot_duration: f32,
 
-    /// how fast the enemy walk.
+    /// how fast the enemy walk, in unit/sec.
     pub walk_speed: f32,
 
-    pub health: i32,
+    /// How far can the enemy see the player.
+    pub view_distance: f32,
+
+    /// Angle of the vision cone in radians.
+    #[serde(rename = "fov_deg", with = "degrees")]
+    pub fov: f32,
+
+    /// How long does the player need to stay in sight before the enemy attacks.
+    pub suspicion_time: f32,
+
+    /// How long does the enemy look for the player after losing sight of them.
+    pub search_duration: f32,
 }
 
 impl Default for SimpleEnemyConfig {
     fn default() -> Self {
         Self {
-            bullet_speed: 100.0,
+            bullet: String::from("enemy"),
             walk_duration: 2.0,
             shoot_duration: 1.0,
-            walk_speed: 0.2,
-            health: 2,
+            walk_speed: 12.0,
+            view_distance: 120.0,
+            fov: std::f32::consts::FRAC_PI_2,
+            suspicion_time: 0.5,
+            search_duration: 4.0,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
-pub struct CreepyFirstBossConfig {
-    pub health: i32,
+// ---------------------------------------------------------
+
+/// Bullet patterns that can be fired by a `BulletEmitter`. Patterns can refer to other
+/// patterns by name.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct PatternConfig {
+    pub patterns: HashMap<String, Vec<PatternStep>>,
+}
+
+/// One step of a bullet pattern. Angles are in radians (degrees in the configuration file).
+/// 0 is the direction of the emitter, which starts facing down.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum PatternStep {
+    /// Fire `count` bullets evenly distributed around the emitter.
+    Ring { count: usize, bullet: BulletStep },
+
+    /// Fire `count` bullets spread over `angle`. If `aimed`, the spread is centered on the
+    /// player.
+    Spread {
+        count: usize,
+        #[serde(rename = "angle_deg", with = "degrees")]
+        angle: f32,
+        #[serde(default)]
+        aimed: bool,
+        bullet: BulletStep,
+    },
+
+    /// Fire `count` bullets one after the other, rotating the emitter by `angle_step` between
+    /// each bullet.
+    Spiral {
+        count: usize,
+        #[serde(rename = "angle_step_deg", with = "degrees")]
+        angle_step: f32,
+        delay: f32,
+        bullet: BulletStep,
+    },
+
+    /// Fire `count` bullets one after the other in the same direction.
+    Burst {
+        count: usize,
+        delay: f32,
+        #[serde(default)]
+        aimed: bool,
+        bullet: BulletStep,
+    },
+
+    /// Wait some seconds.
+    Wait(f32),
+
+    /// Rotate the emitter.
+    Rotate {
+        #[serde(rename = "angle_deg", with = "degrees")]
+        angle: f32,
+    },
+
+    /// Repeat the steps a few times.
+    Repeat {
+        times: usize,
+        steps: Vec<PatternStep>,
+    },
+
+    /// Execute the pattern with the given name.
+    Pattern(String),
+}
+
+/// Bullet fired by a pattern step. The speed and behavior of the bullet archetype
+/// can be overridden.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BulletStep {
+    /// Name of the bullet (in bullets.ron)
+    pub name: String,
+    #[serde(default)]
+    pub speed: Option<f32>,
+    #[serde(default)]
+    pub behavior: Option<BulletBehavior>,
+}
+
+impl PatternConfig {
+    /// Names of all the bullets used by the patterns.
+    pub fn bullet_names(&self) -> Vec<&str> {
+        fn collect<'a>(steps: &'a [PatternStep], names: &mut Vec<&'a str>) {
+            for step in steps {
+                match *step {
+                    PatternStep::Ring { ref bullet, .. }
+                    | PatternStep::Spread { ref bullet, .. }
+                    | PatternStep::Spiral { ref bullet, .. }
+                    | PatternStep::Burst { ref bullet, .. } => names.push(&bullet.name),
+                    PatternStep::Repeat { ref steps, .. } => collect(steps, names),
+                    PatternStep::Wait(_)
+                    | PatternStep::Rotate { .. }
+                    | PatternStep::Pattern(_) => (),
+                }
+            }
+        }
+
+        let mut names = vec![];
+        for steps in self.patterns.values() {
+            collect(steps, &mut names);
+        }
+        names
+    }
+}
+
+// ---------------------------------------------------------
+
+/// All the bullets that can be spawned, by name. Loaded from assets/prefab/bullets.ron
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct BulletConfig {
+    pub bullets: HashMap<String, BulletArchetype>,
+}
+
+/// Description of a kind of bullet.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BulletArchetype {
+    /// Name of the spritesheet in the texture folder.
+    pub sprite_sheet: String,
+    pub sprite_number: usize,
+
+    /// Size of the square collider.
     pub collider_size: f32,
-    /// nb of places where the boss will shoot the bullet.
-    /// Distributed around him
-    pub bullet_spawn: usize,
+
+    /// Speed in unit/sec
+    pub speed: f32,
+
+    /// Damage dealt to the entity that is hit.
+    pub damage: Damage,
+
+    #[serde(default)]
+    pub behavior: BulletBehavior,
+
+    /// The bullet disappears after that many seconds.
+    #[serde(default)]
+    pub lifetime: Option<f32>,
+
+    /// The bullet disappears after flying that distance.
+    #[serde(default)]
+    pub max_range: Option<f32>,
+
+    /// Number of times the bullet bounces off the walls before disappearing.
+    #[serde(default)]
+    pub bounces: u32,
+
+    /// Number of entities the bullet goes through before disappearing.
+    #[serde(default)]
+    pub pierce: u32,
+
+    /// Speed given to the entity that is hit, in unit/sec.
+    #[serde(default)]
+    pub knockback: f32,
+}
+
+// ---------------------------------------------------------
+
+/// All the weapons that the player can use, by name. Loaded from assets/prefab/weapons.ron
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WeaponConfig {
+    pub weapons: HashMap<String, WeaponArchetype>,
+
+    /// Weapons in the inventory of the player at the start of the game. The first one is
+    /// equipped.
+    pub starting_weapons: Vec<String>,
 }
 
-impl Default for CreepyFirstBossConfig {
+impl Default for WeaponConfig {
     fn default() -> Self {
+        let mut weapons = HashMap::new();
+        weapons.insert(
+            String::from("pistol"),
+            WeaponArchetype {
+                bullet: String::from("player"),
+                fire_rate: 1.0,
+                projectiles: 1,
+                spread: 0.0,
+                magazine_size: None,
+                reload_time: 0.0,
+                ammo: None,
+            },
+        );
         Self {
-            health: 10,
-            collider_size: 48.0,
-            bullet_spawn: 4,
+            weapons,
+            starting_weapons: vec![String::from("pistol")],
         }
     }
 }
 
+/// Description of a kind of weapon.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WeaponArchetype {
+    /// Name of the bullet (in bullets.ron)
+    pub bullet: String,
+
+    /// Shots per second.
+    pub fire_rate: f32,
+
+    /// Number of bullets fired by one shot.
+    pub projectiles: usize,
+
+    /// Angle in radians covered by the bullets of one shot.
+    #[serde(default, rename = "spread_deg", with = "degrees")]
+    pub spread: f32,
+
+    /// Number of shots before reloading. No magazine means the weapon never reloads.
+    #[serde(default)]
+    pub magazine_size: Option<u32>,
+
+    /// How long it takes to refill the magazine, in seconds.
+    #[serde(default)]
+    pub reload_time: f32,
+
+    /// Shots available outside of the magazine when the weapon is picked up. No ammo means
+    /// infinite ammo.
+    #[serde(default)]
+    pub ammo: Option<u32>,
+}
+
+impl WeaponConfig {
+    /// Names of all the bullets fired by the weapons.
+    pub fn bullet_names(&self) -> Vec<&str> {
+        self.weapons
+            .values()
+            .map(|weapon| weapon.bullet.as_str())
+            .collect()
+    }
+}
+
 // ---------------------------------------------------------
 
-#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy)]
-pub struct BulletConfig {
-    pub simple_enemy: SimpleBulletConfig,
-    pub rotating_bullet: RotatingBulletConfig,
+/// Designate one of the two objects of a collision response. `First` is the object
+/// which type is `CollisionResponse::first`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionSide {
+    First,
+    Second,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
-pub struct SimpleBulletConfig;
+impl CollisionSide {
+    /// Return the element of the pair that corresponds to this side.
+    pub fn pick<'a, T>(self, first: &'a T, second: &'a T) -> &'a T {
+        match self {
+            CollisionSide::First => first,
+            CollisionSide::Second => second,
+        }
+    }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
-pub struct RotatingBulletConfig {
-    rot_per_sec: f32,
+    /// The other side of the pair.
+    pub fn other(self) -> Self {
+        match self {
+            CollisionSide::First => CollisionSide::Second,
+            CollisionSide::Second => CollisionSide::First,
+        }
+    }
+}
+
+/// What to do when two objects start colliding.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum CollisionAction {
+    /// Remove the entity of the given side.
+    Despawn(CollisionSide),
+    /// Send an `EntityHit` event for the entity of the given side. The damage is the one of
+    /// the bullet or the melee hitbox on the other side.
+    Hit(CollisionSide),
+    /// Send an event.
+    Emit(EventConfig),
+    /// The bullet of the given side bounces off the other object. It is removed when it
+    /// cannot bounce anymore.
+    Bounce(CollisionSide),
+    /// The bullet of the given side goes through the other object. It is removed when it
+    /// cannot pierce anymore.
+    Pierce(CollisionSide),
+    /// The pickup of the given side is collected by the other object.
+    Collect(CollisionSide),
+}
+
+/// Actions to run when an object of type `first` collides with an object of type `second`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CollisionResponse {
+    pub first: ColliderObjectType,
+    pub second: ColliderObjectType,
+    pub actions: Vec<CollisionAction>,
 }
 
-impl Default for RotatingBulletConfig {
+impl CollisionResponse {
+    fn new(
+        first: ColliderObjectType,
+        second: ColliderObjectType,
+        actions: Vec<CollisionAction>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            actions,
+        }
+    }
+}
+
+/// Table of responses used by the collision system. A pair of types that is not in the
+/// table is just ignored.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CollisionConfig {
+    pub responses: Vec<CollisionResponse>,
+}
+
+impl CollisionConfig {
+    /// Find the response for a pair of collider types. The order of the pair does not matter. The
+    /// returned boolean is true when the response was written as (ty2, ty1), in which case
+    /// `CollisionSide::First` designates the object of type `ty2`.
+    pub fn find(
+        &self,
+        ty1: ColliderObjectType,
+        ty2: ColliderObjectType,
+    ) -> Option<(&CollisionResponse, bool)> {
+        self.responses.iter().find_map(|response| {
+            if response.first == ty1 && response.second == ty2 {
+                Some((response, false))
+            } else if response.first == ty2 && response.second == ty1 {
+                Some((response, true))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Default for CollisionConfig {
     fn default() -> Self {
-        Self { rot_per_sec: 1.0 }
+        Self {
+            responses: vec![
+                CollisionResponse::new(
+                    ColliderObjectType::Bullet,
+                    ColliderObjectType::Player,
+                    vec![
+                        CollisionAction::Hit(CollisionSide::Second),
+                        CollisionAction::Pierce(CollisionSide::First),
+                    ],
+                ),
+                CollisionResponse::new(
+                    ColliderObjectType::Bullet,
+                    ColliderObjectType::Wall,
+                    vec![CollisionAction::Bounce(CollisionSide::First)],
+                ),
+                CollisionResponse::new(
+                    ColliderObjectType::Bullet,
+                    ColliderObjectType::Enemy,
+                    vec![
+                        CollisionAction::Hit(CollisionSide::Second),
+                        CollisionAction::Pierce(CollisionSide::First),
+                    ],
+                ),
+                CollisionResponse::new(
+                    ColliderObjectType::Pickup,
+                    ColliderObjectType::Player,
+                    vec![CollisionAction::Collect(CollisionSide::First)],
+                ),
+                CollisionResponse::new(
+                    ColliderObjectType::Melee,
+                    ColliderObjectType::Enemy,
+                    vec![CollisionAction::Hit(CollisionSide::Second)],
+                ),
+            ],
+        }
     }
 }
@@ -5,6 +5,21 @@ pub type GameResult<T> = std::result::Result<T, GameError>;
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
 pub enum GameError {
-    #[snafu(display("Bullet with index {} was not found.", idx))]
-    BulletNotFound { idx: usize },
+    #[snafu(display("Bullet {} was not found.", name))]
+    BulletNotFound { name: String },
+
+    #[snafu(display("Bullet pattern {} was not found.", name))]
+    PatternNotFound { name: String },
+
+    #[snafu(display("Bullet pattern {} refers to itself.", name))]
+    RecursivePattern { name: String },
+
+    #[snafu(display("Bullet pattern {} has more than {} operations.", name, max))]
+    PatternTooLong { name: String, max: usize },
+
+    #[snafu(display("Weapon {} was not found.", name))]
+    WeaponNotFound { name: String },
+
+    #[snafu(display("Enemy {} was not found.", name))]
+    EnemyNotFound { name: String },
 }
@@ -1,12 +1,14 @@
 // These imports are required for the #[derive(EventReader)] code to build
-use crate::systems::schedule::ScheduledEvent;
+use crate::systems::{health::Damage, schedule::ScheduledEvent};
 use amethyst::core::{
     ecs::{Entity, Read, SystemData, World},
+    math::Vector2,
     shrev::{EventChannel, ReaderId},
     EventReader,
 };
 use amethyst::derive::EventReader;
 use amethyst::{ui::UiEvent, winit::Event};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
@@ -23,8 +25,13 @@ pub enum AppEvent {
 
     /// Generated when the game is finished (player loses)
     GameOver,
-    /// Generated when an entity is hit by a bullet.
-    EntityHit(Entity),
+    /// Generated when an entity is hit by a bullet. The attacker is the entity that
+    /// caused the hit (the bullet...), if any.
+    EntityHit {
+        target: Entity,
+        attacker: Option<Entity>,
+        damage: Damage,
+    },
     /// Generated when an entity has died
     EnemyDied(Entity),
     /// Spawn a certain number of enemies
@@ -38,6 +45,53 @@ pub enum AppEvent {
 
     /// All the waves are finshed. Let's start new arena (or boss :D)
     NextArena,
+
+    /// Open the doors with the given name.
+    OpenDoor(String),
+
+    /// An enemy raised the alarm at `origin` because it has seen the player at `target`.
+    Alarm {
+        origin: Vector2<f32>,
+        target: Vector2<f32>,
+    },
+
+    /// Spawn more enemies in the current wave because of the alarm.
+    Reinforcements(i32),
+
+    /// The pickup has been collected by an entity.
+    Collect { pickup: Entity, collector: Entity },
+}
+
+/// Description of an `AppEvent` that can be written in the configuration files. Only the
+/// events that do not need runtime data (entities...) can be described that way.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum EventConfig {
+    /// Start a dialog with the given sentences.
+    Dialog(Vec<String>),
+    GameOver,
+    SpawnEnemy(i32),
+    NextWave,
+    SpawnBoss,
+    NextArena,
+    OpenDoor(String),
+}
+
+impl EventConfig {
+    /// Create the event to send in the event channel.
+    pub fn to_event(&self) -> AppEvent {
+        match *self {
+            EventConfig::Dialog(ref dialog) => AppEvent::NewDialog {
+                dialog: dialog.clone(),
+                and_then: None,
+            },
+            EventConfig::GameOver => AppEvent::GameOver,
+            EventConfig::SpawnEnemy(nb) => AppEvent::SpawnEnemy(nb),
+            EventConfig::NextWave => AppEvent::NextWave,
+            EventConfig::SpawnBoss => AppEvent::SpawnBoss,
+            EventConfig::NextArena => AppEvent::NextArena,
+            EventConfig::OpenDoor(ref name) => AppEvent::OpenDoor(name.clone()),
+        }
+    }
 }
 
 #[derive(Debug, EventReader, Clone)]
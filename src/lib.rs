@@ -7,4 +7,5 @@ pub mod states;
 pub mod systems;
 pub mod tilemap;
 pub mod util;
+pub mod vision;
 pub mod z_layers;
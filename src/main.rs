@@ -11,12 +11,16 @@ use amethyst::{
     utils::application_root_dir,
 };
 use thief_engine::{
-    config::{BulletConfig, CameraConfig, EnemyConfig, PlayerConfig},
+    config::{
+        BulletConfig, CameraConfig, CollisionConfig, EnemyArchetypeConfig, EnemyConfig,
+        PatternConfig, PlayerConfig, WeaponConfig,
+    },
     event::{MyEvent, MyEventReader},
     states,
     systems::{
-        attack, health, schedule, spawn, wave, AnimationSystem, BulletSystem, CollisionSystemDesc,
-        DialogSystem, EnemySystem, MyCollisionWorld, PlayerSystem, WalkableSystem,
+        aim, alarm, attack, door, emitter, health, hud, melee, movement, pickup, schedule, spawn,
+        visibility, wave, AnimationSystem, BulletSystem, CollisionSystemDesc, DialogSystem,
+        EnemySystem, MyCollisionWorld, PlayerSystem, WalkableSystem,
     },
 };
 
@@ -37,11 +41,19 @@ fn main() -> amethyst::Result<()> {
     let config_path = app_root.join("config").join("player.ron");
     let arena_config_path = app_root.join("config").join("camera.ron");
     let enemy_config_path = app_root.join("config").join("enemy.ron");
-    let bullet_config_path = app_root.join("config").join("bullet.ron");
+    let bullet_config_path = app_root.join("assets").join("prefab").join("bullets.ron");
+    let weapon_config_path = app_root.join("assets").join("prefab").join("weapons.ron");
+    let enemy_archetype_path = app_root.join("assets").join("prefab").join("enemies.ron");
+    let collision_config_path = app_root.join("config").join("collision.ron");
+    let pattern_config_path = app_root.join("config").join("patterns.ron");
     let player_config = PlayerConfig::load(&config_path);
     let arena_config = CameraConfig::load(&arena_config_path);
     let enemy_config = EnemyConfig::load(&enemy_config_path);
     let bullet_config = BulletConfig::load(&bullet_config_path);
+    let weapon_config = WeaponConfig::load(&weapon_config_path);
+    let enemy_archetype_config = EnemyArchetypeConfig::load(&enemy_archetype_path);
+    let collision_config = CollisionConfig::load(&collision_config_path);
+    let pattern_config = PatternConfig::load(&pattern_config_path);
 
     let display_config_path = app_root.join("config").join("display.ron");
     let binding_path = app_root.join("config").join("bindings.ron");
@@ -76,6 +88,12 @@ fn main() -> amethyst::Result<()> {
             &["input_system"],
         )
         .with(AnimationSystem, "animation_system", &["player_system"])
+        .with(
+            visibility::VisibilitySystem.pausable(states::RuntimeSystemState::Running),
+            "visibility_system",
+            &["player_system"],
+        )
+        .with(hud::HudSystem, "hud_system", &["visibility_system"])
         .with(DialogSystem, "dialog_system", &["input_system"])
         .with(
             EnemySystem.pausable(states::RuntimeSystemState::Running),
@@ -87,9 +105,16 @@ fn main() -> amethyst::Result<()> {
             "bullet_system",
             &[],
         )
+        .with(
+            emitter::EmitterSystem.pausable(states::RuntimeSystemState::Running),
+            "emitter_system",
+            &[],
+        )
         .with_system_desc(CollisionSystemDesc, "collision_system", &[])
         .with_system_desc(wave::WaveSystemDesc, "wave_system", &[])
         .with_system_desc(spawn::SpawnSystemDesc, "spawn_system", &[])
+        .with_system_desc(door::DoorSystemDesc, "door_system", &[])
+        .with_system_desc(alarm::AlarmSystemDesc, "alarm_system", &["enemy_system"])
         .with(
             WalkableSystem.pausable(states::RuntimeSystemState::Running),
             "walkable_system",
@@ -100,7 +125,31 @@ fn main() -> amethyst::Result<()> {
             "health_system",
             &["collision_system"],
         )
-        .with(attack::AttackSystem, "attack_system", &["input_system"])
+        .with(
+            aim::AimSystem::default().pausable(states::RuntimeSystemState::Running),
+            "aim_system",
+            &["input_system"],
+        )
+        .with(
+            attack::AttackSystem.pausable(states::RuntimeSystemState::Running),
+            "attack_system",
+            &["aim_system"],
+        )
+        .with(
+            melee::MeleeSystem.pausable(states::RuntimeSystemState::Running),
+            "melee_system",
+            &["aim_system"],
+        )
+        .with_system_desc(
+            pickup::PickupSystemDesc,
+            "pickup_system",
+            &["collision_system"],
+        )
+        .with_system_desc(
+            movement::MovementSystemDesc,
+            "movement_system",
+            &["collision_system", "player_system", "enemy_system"],
+        )
         .with(schedule::Scheduler, "scheduler", &[]);
 
     let assets_dir = app_root.join("assets");
@@ -112,6 +161,10 @@ fn main() -> amethyst::Result<()> {
     .with_resource(arena_config)
     .with_resource(enemy_config)
     .with_resource(bullet_config)
+    .with_resource(weapon_config)
+    .with_resource(enemy_archetype_config)
+    .with_resource(collision_config)
+    .with_resource(pattern_config)
     .with_resource(collision_world)
     .build(game_data);
 
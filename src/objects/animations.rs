@@ -3,7 +3,6 @@
 use crate::systems::Animation;
 use std::collections::HashMap;
 
-use crate::systems::enemy::EnemyType;
 /// Basic 4-direction walk animation.
 pub fn get_walking_animations() -> HashMap<String, Animation> {
     let down_animation = Animation {
@@ -38,52 +37,14 @@ pub fn get_walking_animations() -> HashMap<String, Animation> {
 
     animations
 }
-pub fn get_enemy_simplest_animation() -> HashMap<String, Animation> {
-    let down_animation = Animation {
-        sprite_indexes: vec![0],
-        current_index: 0,
-        step_duration: 10.0,
-        elapsed_time: 0.0,
-    };
-    let left_animation = Animation {
-        sprite_indexes: vec![0],
-        current_index: 0,
-        step_duration: 10.0,
-        elapsed_time: 0.0,
-    };
-    let right_animation = Animation {
-        sprite_indexes: vec![0],
-        current_index: 0,
-        step_duration: 10.3,
-        elapsed_time: 0.0,
-    };
-    let up_animation = Animation {
-        sprite_indexes: vec![2],
-        current_index: 0,
-        step_duration: 10.3,
-        elapsed_time: 0.0,
-    };
-
-    let shoot_animation = Animation {
-        sprite_indexes: vec![1],
-        current_index: 0,
-        step_duration: 3.0,
-        elapsed_time: 0.0,
-    };
-    let mut animations = HashMap::new();
-    animations.insert("walk_down".to_string(), down_animation);
-    animations.insert("walk_left".to_string(), left_animation);
-    animations.insert("walk_right".to_string(), right_animation);
-    animations.insert("walk_up".to_string(), up_animation);
-    animations.insert("shoot".to_string(), shoot_animation);
-
-    animations
-}
 
-/// Will return the correct animation controller for each enemy.
-pub fn get_enemy_anim(enemy_type: EnemyType) -> Option<HashMap<String, Animation>> {
-    match enemy_type {
-        EnemyType::Simple => Some(get_enemy_simplest_animation()),
-        _ => None,
-    }
+/// Dash animation. Same sprites as the walk animation, but faster.
+pub fn get_dash_animations() -> HashMap<String, Animation> {
+    get_walking_animations()
+        .into_iter()
+        .map(|(name, mut animation)| {
+            animation.step_duration = 0.05;
+            (name.replace("walk", "dash"), animation)
+        })
+        .collect()
 }
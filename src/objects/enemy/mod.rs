@@ -1,13 +1,27 @@
 //! Helpers to create the enemy entities...
 //!
+//! Enemies are described in assets/prefab/enemies.ron and spawned by name.
 use crate::{
-    config::EnemyConfig,
-    objects::animations,
+    config::{
+        DropConfig, EnemyArchetype, EnemyArchetypeConfig, EnemyBehavior, EnemyConfig,
+        PatternConfig, WeaponConfig,
+    },
+    error::{GameError, GameResult},
     systems::{
-        enemy::EnemyType, health::Health, AnimationController, Collider, ColliderObjectType, Enemy,
+        emitter::{compile_pattern, BulletEmitter, PatternOp},
+        health::Health,
+        movement::Velocity,
+        pickup::{Loot, WeaponPickup},
+        Animation,
+        AnimationController,
+        Collider,
+        ColliderObjectType,
+        Enemy,
+        Grounded,
         MyCollisionWorld,
     },
     util::load_spritesheet,
+    z_layers::PROPS_LAYER,
 };
 use amethyst::{
     assets::Handle,
@@ -16,34 +30,86 @@ use amethyst::{
     prelude::*,
     renderer::{SpriteRender, SpriteSheet},
 };
-use std::collections::HashMap;
+use log::{debug, error};
+use rand::Rng;
+use std::{collections::HashMap, sync::Arc};
 
 mod simple_enemy;
-pub use simple_enemy::SimpleEnemy;
+pub use simple_enemy::{Awareness, SimpleEnemy};
 mod creepy_boss;
 pub use creepy_boss::CreepyFirstBoss;
 
+/// Size of the collider of the dropped pickups.
+const DROP_SIZE: f32 = 16.0;
+
 /// Enemy spawner will help creating new enemies. It hold the necessary asset handles (e.g.
 /// textures, animation,...) necessary to create new enemies.
 #[derive(Debug, Default)]
 pub struct EnemySpawner {
-    textures: HashMap<EnemyType, Handle<SpriteSheet>>,
-    enemy_config: EnemyConfig,
+    /// Sprite sheets of the enemies and of their drops, by name.
+    textures: HashMap<String, Handle<SpriteSheet>>,
+    archetypes: EnemyArchetypeConfig,
+    /// Compiled bullet patterns, by enemy name.
+    patterns: HashMap<String, Arc<Vec<PatternOp>>>,
 }
 
 impl EnemySpawner {
+    /// Will load the textures and bullet patterns of all the enemies.
     ///
-    pub fn init(world: &mut World) -> Self {
-        // default implementation assumes all assets are present. Doesn't make sense otherwise...
+    /// Fails if an enemy used in the configuration files does not exist, or if an enemy
+    /// refers to a pattern or weapon that does not exist.
+    pub fn init(world: &mut World) -> GameResult<Self> {
+        let archetypes = world.read_resource::<EnemyArchetypeConfig>().clone();
+
+        {
+            let enemy_config = world.read_resource::<EnemyConfig>();
+            if let Some(name) = enemy_config
+                .wave_enemies
+                .iter()
+                .chain(std::iter::once(&enemy_config.boss))
+                .find(|name| !archetypes.enemies.contains_key(*name))
+            {
+                return Err(GameError::EnemyNotFound { name: name.clone() });
+            }
+
+            let weapon_config = world.read_resource::<WeaponConfig>();
+            if let Some(drop) = archetypes
+                .enemies
+                .values()
+                .flat_map(|archetype| archetype.drops.iter())
+                .find(|drop| !weapon_config.weapons.contains_key(&drop.weapon))
+            {
+                return Err(GameError::WeaponNotFound {
+                    name: drop.weapon.clone(),
+                });
+            }
+        }
+
+        let mut patterns = HashMap::new();
+        for (name, archetype) in &archetypes.enemies {
+            if let EnemyBehavior::CreepyFirstBoss { ref pattern } = archetype.behavior {
+                let ops = compile_pattern(&world.read_resource::<PatternConfig>(), pattern)?;
+                patterns.insert(name.clone(), Arc::new(ops));
+            }
+        }
+
         let mut textures = HashMap::new();
-        let sprite_sheet = load_spritesheet("enemy_simple", world);
-        let boss = load_spritesheet("boss_1", world);
-        textures.insert(EnemyType::Simple, sprite_sheet);
-        textures.insert(EnemyType::CreepyFirstBoss, boss);
-        Self {
-            textures,
-            enemy_config: *world.read_resource::<EnemyConfig>(),
+        for archetype in archetypes.enemies.values() {
+            let sprite_sheets = std::iter::once(&archetype.sprite_sheet)
+                .chain(archetype.drops.iter().map(|drop| &drop.sprite_sheet));
+            for sprite_sheet in sprite_sheets {
+                if !textures.contains_key(sprite_sheet) {
+                    let handle = load_spritesheet(sprite_sheet, world);
+                    textures.insert(sprite_sheet.clone(), handle);
+                }
+            }
         }
+
+        Ok(Self {
+            textures,
+            archetypes,
+            patterns,
+        })
     }
 
     /// Will spawn an enemy at the given position. This is when the user
@@ -51,7 +117,7 @@ impl EnemySpawner {
     pub fn create_enemy(
         &self,
         world: &mut World,
-        enemy_type: EnemyType,
+        name: &str,
         position: Transform,
     ) -> Option<Entity> {
         let mut maybe_entity = None;
@@ -62,58 +128,130 @@ impl EnemySpawner {
                 Write<MyCollisionWorld>,
             )| {
                 maybe_entity =
-                    self.spawn_enemy(&entities, &updater, &mut collision, enemy_type, position);
+                    self.spawn_enemy(&entities, &updater, &mut collision, name, position);
             },
         );
         maybe_entity
     }
 
     /// Will spawn an enemy. This is to be called from a system.
+    ///
+    /// Returns None if there is no enemy with that name.
     pub fn spawn_enemy(
         &self,
         entities: &Entities,
         updater: &LazyUpdate,
         collision: &mut MyCollisionWorld,
-        enemy_type: EnemyType,
+        name: &str,
         position: Transform,
     ) -> Option<Entity> {
-        if let Some(handle) = self.textures.get(&enemy_type) {
-            let sprite = SpriteRender {
-                sprite_sheet: handle.clone(),
-                sprite_number: 0,
-            };
+        let archetype = self.archetypes.enemies.get(name)?;
+        let handle = self.textures.get(&archetype.sprite_sheet)?;
+        let sprite = SpriteRender {
+            sprite_sheet: handle.clone(),
+            sprite_number: 0,
+        };
 
-            let entity = entities.create();
-            let walking_animations = animations::get_enemy_anim(enemy_type);
-            if let Some(walking_animations) = walking_animations {
-                let mut animation_controller = AnimationController {
-                    animations: HashMap::new(),
-                    current_animation: None,
-                };
-                animation_controller.animations.extend(walking_animations);
-                updater.insert(entity, animation_controller);
-            }
-            updater.insert(entity, position);
-            updater.insert(entity, sprite);
-            updater.insert(entity, Enemy::from_config(enemy_type, &self.enemy_config));
-            //updater.insert(entity, collider);
-            self.add_collider(updater, entity, collision, enemy_type);
-            self.add_health(updater, entity, enemy_type);
-            Some(entity)
-        } else {
-            None
+        let entity = entities.create();
+        if !archetype.animations.is_empty() {
+            let animation_controller = AnimationController {
+                animations: archetype
+                    .animations
+                    .iter()
+                    .map(|(name, animation)| {
+                        (
+                            name.clone(),
+                            Animation::new(
+                                animation.sprite_indexes.clone(),
+                                animation.step_duration,
+                            ),
+                        )
+                    })
+                    .collect(),
+                current_animation: None,
+            };
+            updater.insert(entity, animation_controller);
+        }
+        updater.insert(entity, position);
+        updater.insert(entity, sprite);
+        updater.insert(entity, Enemy::from_behavior(&archetype.behavior));
+        self.add_collider(updater, entity, collision, archetype);
+        updater.insert(
+            entity,
+            Health::new(archetype.health)
+                .with_resistances(archetype.resistances.clone())
+                .with_invulnerability(archetype.invulnerability),
+        );
+        if let Some(pattern) = self.patterns.get(name) {
+            updater.insert(entity, BulletEmitter::new(Arc::clone(pattern)));
+        }
+        // Only the grounded enemies can be pushed around (and knocked into the pits).
+        let knockback = if archetype.grounded { 1.0 } else { 0.0 };
+        updater.insert(entity, Velocity::default().with_knockback(knockback));
+        if archetype.grounded {
+            updater.insert(entity, Grounded::default());
         }
+        if !archetype.drops.is_empty() {
+            updater.insert(
+                entity,
+                Loot {
+                    drops: archetype.drops.clone(),
+                },
+            );
+        }
+        Some(entity)
     }
 
-    fn add_health(&self, updater: &LazyUpdate, entity: Entity, enemy_type: EnemyType) {
-        match enemy_type {
-            EnemyType::CreepyFirstBoss => {
-                updater.insert(entity, Health::new(self.enemy_config.creepy_boss.health))
+    /// Roll the drops of a dead enemy and spawn the pickups at its position.
+    pub fn spawn_drops(
+        &self,
+        entities: &Entities,
+        updater: &LazyUpdate,
+        collision: &mut MyCollisionWorld,
+        drops: &[DropConfig],
+        position: Vector2<f32>,
+    ) {
+        let mut rng = rand::thread_rng();
+        for drop in drops {
+            if !rng.gen_bool(f64::from(drop.chance.max(0.0).min(1.0))) {
+                continue;
             }
 
-            EnemyType::Simple => {
-                updater.insert(entity, Health::new(self.enemy_config.simple_enemy.health))
-            }
+            let handle = match self.textures.get(&drop.sprite_sheet) {
+                Some(handle) => handle,
+                None => {
+                    error!("No sprite sheet {} for the drop", drop.sprite_sheet);
+                    continue;
+                }
+            };
+            debug!("Drop {} at {:?}", drop.weapon, position);
+            let entity = entities.create();
+            let collider = Collider::new_rect(
+                position,
+                DROP_SIZE,
+                DROP_SIZE,
+                &mut collision.world,
+                ColliderObjectType::Pickup,
+                Some(&[ColliderObjectType::Player]),
+                Some(entity),
+            );
+            let mut t = Transform::default();
+            t.set_translation_xyz(position.x, position.y, PROPS_LAYER);
+            updater.insert(entity, t);
+            updater.insert(
+                entity,
+                SpriteRender {
+                    sprite_sheet: handle.clone(),
+                    sprite_number: drop.sprite_number,
+                },
+            );
+            updater.insert(entity, collider);
+            updater.insert(
+                entity,
+                WeaponPickup {
+                    weapon: drop.weapon.clone(),
+                },
+            );
         }
     }
 
@@ -122,26 +260,17 @@ impl EnemySpawner {
         updater: &LazyUpdate,
         entity: Entity,
         collision: &mut MyCollisionWorld,
-        enemy_type: EnemyType,
+        archetype: &EnemyArchetype,
     ) {
-        let w = if let EnemyType::CreepyFirstBoss = enemy_type {
-            self.enemy_config.creepy_boss.collider_size
-        } else {
-            16.0
-        };
-        let collider = {
-            let collider = Collider::new_rect(
-                Vector2::new(0.0, 0.0),
-                w,
-                w,
-                &mut collision.world,
-                ColliderObjectType::Enemy,
-                None,
-                Some(entity),
-            );
-            //collider.set_entity(&mut collision.world, entity);
-            collider
-        };
+        let collider = Collider::new_rect(
+            Vector2::new(0.0, 0.0),
+            archetype.collider_size,
+            archetype.collider_size,
+            &mut collision.world,
+            ColliderObjectType::Enemy,
+            None,
+            Some(entity),
+        );
         updater.insert(entity, collider);
     }
 }
@@ -1,16 +1,49 @@
-//! Behavior of the base enemy of the base. It will hover slowly
-//! towards the player and shoot bullets at fixed interval.
+//! Behavior of the base enemy of the base. It is guarding its position until it
+//! sees the player. Then it will hover slowly towards the player and shoot bullets
+//! at fixed interval.
 
 use amethyst::{
-    core::{math::Vector3, Transform},
+    core::{
+        math::{Point2, Rotation2, Vector2, Vector3},
+        Transform,
+    },
     ecs::{Entities, LazyUpdate, Read, Write},
 };
 
 use crate::{
     config::SimpleEnemyConfig,
-    systems::{animation::AnimationController, bullet::BulletSpawner, MyCollisionWorld},
+    event::AppEvent,
+    systems::{
+        animation::AnimationController, bullet::BulletSpawner, movement::Velocity,
+        visibility::PlayerVisibility, MyCollisionWorld,
+    },
+    vision::LineOfSight,
 };
-use log::error;
+use log::{debug, error};
+
+/// How fast the enemy turns its head when searching for the player, in radian per second.
+const SEARCH_TURN_SPEED: f32 = std::f32::consts::FRAC_PI_2;
+
+/// Distance under which the enemy considers it has reached the last known position.
+const SEARCH_ARRIVAL_DISTANCE: f32 = 4.0;
+
+/// How long a suspicious enemy waits after losing sight of the player before its suspicion
+/// starts going down, in seconds. Without it, the enemy flickers between unaware and
+/// suspicious when the player is on the edge of its vision.
+const SUSPICION_GRACE_TIME: f32 = 0.5;
+
+/// What the enemy knows about the player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Awareness {
+    /// Does not know the player is there.
+    Unaware,
+    /// Caught a glimpse of the player. It will attack if the player stays in sight.
+    Suspicious,
+    /// Knows where the player is and attacks.
+    Alerted,
+    /// Lost sight of the player and goes to the last known position.
+    Searching,
+}
 
 #[derive(Debug, Clone, Copy)]
 enum EnemyStatus {
@@ -20,12 +53,12 @@ enum EnemyStatus {
 }
 
 /// Data for the simple enemy.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct SimpleEnemy {
-    /// How fast its bullet flies.
-    bullet_speed: f32,
+    /// Name of its bullet.
+    bullet: String,
 
-    /// how fast it walks.
+    /// how fast it walks, in unit/sec.
     walk_speed: f32,
 
     /// what the enemy is currently doing
@@ -40,18 +73,56 @@ pub struct SimpleEnemy {
 
     /// Shoot duration. How long does the enemy shoot? It is related to the shooting animation.
     shoot_duration: f32,
+
+    /// What the enemy knows about the player.
+    awareness: Awareness,
+
+    /// How long have we been in the current awareness state. When suspicious, this goes
+    /// down when the player is not in sight.
+    awareness_duration: f32,
+
+    /// How long has the player been out of sight while the enemy is suspicious.
+    unseen_duration: f32,
+
+    /// Where the enemy is looking.
+    facing: Vector2<f32>,
+
+    /// Where the player was when the enemy saw them for the last time.
+    last_known_position: Option<Vector2<f32>>,
+
+    /// How far can the enemy see.
+    view_distance: f32,
+
+    /// Angle of the vision cone in radian.
+    fov: f32,
+
+    /// How long does the player need to stay in sight before the enemy attacks.
+    suspicion_time: f32,
+
+    /// How long does the enemy look for the player after losing sight of them.
+    search_duration: f32,
 }
 
 impl Default for SimpleEnemy {
     fn default() -> Self {
         Self {
-            bullet_speed: 100.0,
+            bullet: String::from("enemy"),
             state: EnemyStatus::Walking,
 
-            walk_speed: 0.2,
+            walk_speed: 12.0,
             current_state_duration: 0.0,
             walk_duration: 3.0,
             shoot_duration: 0.3,
+
+            awareness: Awareness::Unaware,
+            awareness_duration: 0.0,
+            unseen_duration: 0.0,
+            facing: Vector2::new(0.0, -1.0),
+            last_known_position: None,
+            view_distance: 120.0,
+            fov: std::f32::consts::FRAC_PI_2,
+            suspicion_time: 0.5,
+            search_duration: 4.0,
         }
     }
 }
@@ -59,35 +130,233 @@ impl Default for SimpleEnemy {
 impl SimpleEnemy {
     pub fn from_config(config: &SimpleEnemyConfig) -> Self {
         Self {
-            bullet_speed: config.bullet_speed,
+            bullet: config.bullet.clone(),
+            walk_speed: config.walk_speed,
             walk_duration: config.walk_duration,
             shoot_duration: config.shoot_duration,
+            view_distance: config.view_distance,
+            fov: config.fov,
+            suspicion_time: config.suspicion_time,
+            search_duration: config.search_duration,
             ..SimpleEnemy::default()
         }
     }
 
-    /// Simple enemy can be in three states. Either moving, shooting or dying.
+    pub fn awareness(&self) -> Awareness {
+        self.awareness
+    }
+
+    /// Check whether the player is in the vision cone of the enemy and not hidden
+    /// behind an obstacle.
+    ///
+    /// Until the enemy is alerted, the view distance depends on the visibility of the
+    /// player so that it is possible to sneak in the shadows.
+    pub fn can_see(
+        &self,
+        t: &Transform,
+        player_vec: &Vector3<f32>,
+        visibility: &PlayerVisibility,
+        line_of_sight: &LineOfSight,
+    ) -> bool {
+        let view_distance = match self.awareness {
+            Awareness::Alerted => self.view_distance,
+            _ => self.view_distance * visibility.score,
+        };
+        line_of_sight.can_see(
+            Point2::from(t.translation().xy()),
+            self.facing,
+            view_distance,
+            self.fov,
+            Point2::from(player_vec.xy()),
+        )
+    }
+
+    /// Whether an attacker at `attacker_position` can take down the enemy silently. It has
+    /// to be behind the enemy, within `max_angle` (radians), and the enemy must not know the
+    /// player is around.
+    pub fn can_be_taken_down(
+        &self,
+        t: &Transform,
+        attacker_position: Vector2<f32>,
+        max_angle: f32,
+    ) -> bool {
+        let to_attacker = attacker_position - t.translation().xy();
+        self.awareness == Awareness::Unaware
+            && to_attacker.norm() > std::f32::EPSILON
+            && (-self.facing).angle(&to_attacker) <= max_angle / 2.0
+    }
+
+    /// Another enemy raised the alarm. Go check where the player has been seen.
+    pub fn alert(&mut self, target: Vector2<f32>) {
+        self.last_known_position = Some(target);
+        if self.awareness != Awareness::Alerted {
+            debug!("Enemy alerted by alarm");
+            self.awareness = Awareness::Searching;
+            self.awareness_duration = 0.0;
+        }
+    }
+
+    /// Update what the enemy knows about the player. Return true if the enemy just spotted
+    /// the player.
+    fn update_awareness(
+        &mut self,
+        delta_time: f32,
+        player_vec: &Vector3<f32>,
+        player_visible: bool,
+    ) -> bool {
+        let previous = self.awareness;
+        if player_visible {
+            self.last_known_position = Some(player_vec.xy());
+        }
+
+        match self.awareness {
+            Awareness::Unaware => {
+                if player_visible {
+                    self.awareness = Awareness::Suspicious;
+                    self.awareness_duration = 0.0;
+                    self.unseen_duration = 0.0;
+                }
+            }
+            Awareness::Suspicious => {
+                if player_visible {
+                    self.unseen_duration = 0.0;
+                    self.awareness_duration += delta_time;
+                    if self.awareness_duration >= self.suspicion_time {
+                        self.awareness = Awareness::Alerted;
+                        self.awareness_duration = 0.0;
+                    }
+                } else if self.unseen_duration < SUSPICION_GRACE_TIME {
+                    self.unseen_duration += delta_time;
+                } else {
+                    self.awareness_duration -= delta_time;
+                    if self.awareness_duration <= 0.0 {
+                        self.awareness = Awareness::Unaware;
+                        self.awareness_duration = 0.0;
+                    }
+                }
+            }
+            Awareness::Alerted => {
+                if !player_visible {
+                    self.awareness = Awareness::Searching;
+                    self.awareness_duration = 0.0;
+                }
+            }
+            Awareness::Searching => {
+                if player_visible {
+                    self.awareness = Awareness::Alerted;
+                    self.awareness_duration = 0.0;
+                } else {
+                    self.awareness_duration += delta_time;
+                    if self.awareness_duration >= self.search_duration {
+                        self.awareness = Awareness::Unaware;
+                        self.awareness_duration = 0.0;
+                        self.last_known_position = None;
+                    }
+                }
+            }
+        }
+
+        if previous != self.awareness {
+            debug!("Enemy went from {:?} to {:?}", previous, self.awareness);
+            // Start the attack by walking towards the player.
+            self.state = EnemyStatus::Walking;
+            self.current_state_duration = 0.0;
+        }
+
+        previous != self.awareness && self.awareness == Awareness::Alerted
+    }
+
+    /// Simple enemy does not do anything until it sees the player. If the player stays in sight
+    /// long enough, it will attack. If it loses sight of the player, it will walk to the last
+    /// known position and look around before going back to being unaware.
+    ///
+    /// When attacking, it can be in three states. Either moving, shooting or dying.
     /// Each states has its own animations.
     /// Walking, it is just hovering towards the player.
     /// Shooting will take a few frames. First it opens the mouth and a bullet will come out of it.
     /// When the enemy has been killed, there is a death animation before the entity is removed.
+    ///
+    /// Return the alarm event when the enemy spots the player.
     pub fn update(
         &mut self,
         delta_time: f32,
         t: &mut Transform,
+        velocity: &mut Velocity,
         maybe_anim: &mut Option<&mut AnimationController>,
         player_vec: &Vector3<f32>,
+        player_visible: bool,
         bullet_spawner: &Read<BulletSpawner>,
         entities: &Entities,
         updater: &Read<LazyUpdate>,
         collision: &mut Write<MyCollisionWorld>,
-    ) {
-        let enemy_vec = t.translation();
+    ) -> Option<AppEvent> {
+        let alarm = if self.update_awareness(delta_time, player_vec, player_visible) {
+            Some(AppEvent::Alarm {
+                origin: t.translation().xy(),
+                target: player_vec.xy(),
+            })
+        } else {
+            None
+        };
 
         let mut previous_animation = None;
         if let Some(ref mut anim) = maybe_anim {
             previous_animation = anim.current_animation.take();
         }
+
+        match self.awareness {
+            Awareness::Unaware => self.set_facing_animation(maybe_anim, previous_animation),
+            Awareness::Suspicious => {
+                // Turn towards the player to get a better look.
+                if let Some(position) = self.last_known_position {
+                    self.look_at(t, position);
+                }
+                self.set_facing_animation(maybe_anim, previous_animation);
+            }
+            Awareness::Searching => {
+                if let Some(position) = self.last_known_position {
+                    let direction = position - t.translation().xy();
+                    if direction.norm() > SEARCH_ARRIVAL_DISTANCE {
+                        self.walk(velocity, direction.normalize());
+                    } else {
+                        // Look around.
+                        self.facing = Rotation2::new(SEARCH_TURN_SPEED * delta_time) * self.facing;
+                    }
+                }
+                self.set_facing_animation(maybe_anim, previous_animation);
+            }
+            Awareness::Alerted => self.attack(
+                delta_time,
+                t,
+                velocity,
+                maybe_anim,
+                previous_animation,
+                player_vec,
+                bullet_spawner,
+                entities,
+                updater,
+                collision,
+            ),
+        }
+
+        alarm
+    }
+
+    /// Walk towards the player and shoot at fixed interval.
+    fn attack(
+        &mut self,
+        delta_time: f32,
+        t: &mut Transform,
+        velocity: &mut Velocity,
+        maybe_anim: &mut Option<&mut AnimationController>,
+        previous_animation: Option<String>,
+        player_vec: &Vector3<f32>,
+        bullet_spawner: &Read<BulletSpawner>,
+        entities: &Entities,
+        updater: &Read<LazyUpdate>,
+        collision: &mut Write<MyCollisionWorld>,
+    ) {
+        let enemy_vec = t.translation();
         let direction = player_vec - enemy_vec;
 
         //let dist = direction.norm();
@@ -96,20 +365,8 @@ impl SimpleEnemy {
         self.current_state_duration += delta_time;
         match self.state {
             EnemyStatus::Walking => {
-                t.prepend_translation_x(self.walk_speed * d.x);
-                t.prepend_translation_y(self.walk_speed * d.y);
-
-                if let Some(ref mut anim) = maybe_anim {
-                    if d.x < 0.0 {
-                        anim.current_animation = Some("walk_left".to_string());
-                    } else if d.x > 0.0 {
-                        anim.current_animation = Some("walk_right".to_string());
-                    } else if d.y > 0.0 {
-                        anim.current_animation = Some("walk_up".to_string());
-                    } else if d.y < 0.0 {
-                        anim.current_animation = Some("walk_down".to_string());
-                    }
-                }
+                self.walk(velocity, d.xy());
+                self.set_facing_animation(maybe_anim, previous_animation);
 
                 // state transition if walked too long
                 if self.current_state_duration >= self.walk_duration {
@@ -124,10 +381,9 @@ impl SimpleEnemy {
                     entities,
                     updater,
                     collision,
-                    0,
+                    &self.bullet,
                     *t.translation(),
                     direction.xy(),
-                    self.bullet_speed,
                 ) {
                     error!("Enemy cannot spawn bullet: {}", e);
                 }
@@ -159,4 +415,159 @@ impl SimpleEnemy {
             }
         }
     }
+
+    /// Move in the given direction. The movement system makes it slide along the walls and
+    /// stops grounded enemies at the edge of the pits.
+    fn walk(&mut self, velocity: &mut Velocity, direction: Vector2<f32>) {
+        velocity.desired = self.walk_speed * direction;
+        self.facing = direction;
+    }
+
+    fn look_at(&mut self, t: &Transform, position: Vector2<f32>) {
+        let direction = position - t.translation().xy();
+        if direction.norm() > std::f32::EPSILON {
+            self.facing = direction.normalize();
+        }
+    }
+
+    /// The walking animations also show where the enemy is looking.
+    fn set_facing_animation(
+        &self,
+        maybe_anim: &mut Option<&mut AnimationController>,
+        previous_animation: Option<String>,
+    ) {
+        if let Some(ref mut anim) = maybe_anim {
+            let d = self.facing;
+            if d.x < 0.0 {
+                anim.current_animation = Some("walk_left".to_string());
+            } else if d.x > 0.0 {
+                anim.current_animation = Some("walk_right".to_string());
+            } else if d.y > 0.0 {
+                anim.current_animation = Some("walk_up".to_string());
+            } else if d.y < 0.0 {
+                anim.current_animation = Some("walk_down".to_string());
+            } else {
+                anim.current_animation = previous_animation;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EnemyArchetypeConfig, EnemyBehavior};
+
+    const ARCHETYPES: &str = r#"(
+        enemies: {
+            "slow": (
+                sprite_sheet: "enemy_simple",
+                collider_size: 16.0,
+                health: 2,
+                behavior: Simple((
+                    bullet: "enemy",
+                    walk_duration: 2.0,
+                    shoot_duration: 1.0,
+                    walk_speed: 10.0,
+                    view_distance: 100.0,
+                    fov_deg: 90.0,
+                    suspicion_time: 0.5,
+                    search_duration: 4.0,
+                )),
+            ),
+            "fast": (
+                sprite_sheet: "enemy_simple",
+                collider_size: 16.0,
+                health: 5,
+                behavior: Simple((
+                    bullet: "boss",
+                    walk_duration: 3.0,
+                    shoot_duration: 0.5,
+                    walk_speed: 30.0,
+                    view_distance: 200.0,
+                    fov_deg: 120.0,
+                    suspicion_time: 0.2,
+                    search_duration: 8.0,
+                )),
+            ),
+        },
+    )"#;
+
+    fn simple_enemy(config: &EnemyArchetypeConfig, name: &str) -> SimpleEnemy {
+        match config.enemies[name].behavior {
+            EnemyBehavior::Simple(ref behavior) => SimpleEnemy::from_config(behavior),
+            ref other => panic!("{} should be a simple enemy, got {:?}", name, other),
+        }
+    }
+
+    const FRAME: f32 = 0.05;
+
+    /// Update the awareness for `duration` seconds, one frame at a time.
+    fn watch(enemy: &mut SimpleEnemy, duration: f32, player_visible: bool) -> bool {
+        let frames = (duration / FRAME).round() as usize;
+        let player = Vector3::new(0.0, -50.0, 0.0);
+        let mut spotted = false;
+        for _ in 0..frames {
+            spotted |= enemy.update_awareness(FRAME, &player, player_visible);
+        }
+        spotted
+    }
+
+    #[test]
+    fn player_on_the_edge_of_vision_keeps_the_enemy_suspicious() {
+        let mut enemy = SimpleEnemy::default();
+        for _ in 0..10 {
+            watch(&mut enemy, FRAME, true);
+            assert_eq!(enemy.awareness(), Awareness::Suspicious);
+            watch(&mut enemy, FRAME, false);
+            assert_eq!(enemy.awareness(), Awareness::Suspicious);
+        }
+    }
+
+    #[test]
+    fn suspicion_goes_down_after_the_grace_time() {
+        let mut enemy = SimpleEnemy::default();
+        watch(&mut enemy, 0.2, true);
+        watch(&mut enemy, SUSPICION_GRACE_TIME, false);
+        assert_eq!(enemy.awareness(), Awareness::Suspicious);
+        watch(&mut enemy, 0.5, false);
+        assert_eq!(enemy.awareness(), Awareness::Unaware);
+    }
+
+    #[test]
+    fn enemy_attacks_then_searches_then_gives_up() {
+        let mut enemy = SimpleEnemy::default();
+        assert!(!watch(&mut enemy, 0.4, true));
+        assert_eq!(enemy.awareness(), Awareness::Suspicious);
+        assert!(watch(&mut enemy, 0.2, true));
+        assert_eq!(enemy.awareness(), Awareness::Alerted);
+        assert!(!watch(&mut enemy, 1.0, true));
+
+        watch(&mut enemy, FRAME, false);
+        assert_eq!(enemy.awareness(), Awareness::Searching);
+        assert!(watch(&mut enemy, FRAME, true));
+        assert_eq!(enemy.awareness(), Awareness::Alerted);
+
+        let search_duration = enemy.search_duration;
+        watch(&mut enemy, search_duration + FRAME * 2.0, false);
+        assert_eq!(enemy.awareness(), Awareness::Unaware);
+        assert_eq!(enemy.last_known_position, None);
+    }
+
+    #[test]
+    fn archetypes_behave_differently() {
+        let config: EnemyArchetypeConfig = ron::de::from_str(ARCHETYPES).unwrap();
+        let mut slow = simple_enemy(&config, "slow");
+        let mut fast = simple_enemy(&config, "fast");
+
+        assert!(watch(&mut fast, 0.3, true));
+        assert!(!watch(&mut slow, 0.3, true));
+        assert_eq!(slow.awareness(), Awareness::Suspicious);
+
+        let mut slow_velocity = Velocity::default();
+        let mut fast_velocity = Velocity::default();
+        slow.walk(&mut slow_velocity, Vector2::new(1.0, 0.0));
+        fast.walk(&mut fast_velocity, Vector2::new(1.0, 0.0));
+        assert!(fast_velocity.desired.norm() > slow_velocity.desired.norm());
+    }
 }
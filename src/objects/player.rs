@@ -1,11 +1,11 @@
 //! Helpers to create the player entity...
 //!
 use crate::{
-    config::PlayerConfig,
+    config::{PlayerConfig, WeaponConfig},
     objects::animations,
     systems::{
-        attack::Weapon, health::Health, AnimationController, Collider, ColliderObjectType,
-        MyCollisionWorld, Player,
+        attack::Inventory, health::Health, melee::MeleeAttack, movement::Velocity,
+        AnimationController, Collider, ColliderObjectType, MyCollisionWorld, Player,
     },
     util::load_spritesheet,
 };
@@ -28,9 +28,12 @@ pub fn create_player(world: &mut World, position: Transform) -> Entity {
         sprite_number: 0,
     };
 
-    let player_config: PlayerConfig = *world
-        .get_mut()
-        .expect("World should have the player config resource");
+    let player_config: PlayerConfig = world
+        .get_mut::<PlayerConfig>()
+        .expect("World should have the player config resource")
+        .clone();
+    let inventory = Inventory::from_config(&world.read_resource::<WeaponConfig>())
+        .expect("Invalid weapon configuration");
 
     // SETUP ANIMATION
     // --------------------------------------------------------
@@ -42,6 +45,9 @@ pub fn create_player(world: &mut World, position: Transform) -> Entity {
         current_animation: None,
     };
     animation_controller.animations.extend(walking_animations);
+    animation_controller
+        .animations
+        .extend(animations::get_dash_animations());
 
     // SETUP COLLIDER
     // --------------------
@@ -69,8 +75,14 @@ pub fn create_player(world: &mut World, position: Transform) -> Entity {
         .with(sprite_render)
         .with(animation_controller)
         .with(collider.clone())
-        .with(Health::new(player_config.health))
-        .with(Weapon::default())
+        .with(
+            Health::new(player_config.health)
+                .with_resistances(player_config.resistances)
+                .with_invulnerability(player_config.invulnerability),
+        )
+        .with(inventory)
+        .with(MeleeAttack::default())
+        .with(Velocity::default())
         .build();
 
     let collision_world = world.get_mut::<MyCollisionWorld>().unwrap();
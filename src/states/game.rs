@@ -6,8 +6,10 @@ use crate::{
     config::{ArenaConfig, CameraConfig},
     event::{AppEvent, MyEvent},
     objects::{enemy::EnemySpawner, player::create_player},
-    systems::{wave, Bullet, Collider, Enemy, MyCollisionWorld, PlayerResource},
-    util::delete_entity_with_collider,
+    systems::{
+        alarm::AlarmLevel, hud::create_hud, melee::MeleeHitbox, pickup::WeaponPickup,
+        visibility::PlayerVisibility, wave, Bullet, Enemy, PlayerResource,
+    },
 };
 use amethyst::{
     core::transform::Transform,
@@ -39,11 +41,13 @@ impl State<GameData<'static, 'static>, MyEvent> for GameState {
         let world = data.world;
 
         debug!("Add Bullet spawner resource");
-        let bullet_spawner = BulletSpawner::init(world);
+        let bullet_spawner = BulletSpawner::init(world).expect("Invalid bullet configuration");
         world.insert(bullet_spawner);
-        let enemy_spawner = EnemySpawner::init(world);
+        let enemy_spawner = EnemySpawner::init(world).expect("Invalid enemy configuration");
         debug!("Add EnemySpawner resource");
         world.insert(enemy_spawner);
+        world.insert(AlarmLevel::default());
+        world.insert(PlayerVisibility::default());
 
         // Setup debug lines as a resource
         world.insert(DebugLines::new());
@@ -92,6 +96,8 @@ impl State<GameData<'static, 'static>, MyEvent> for GameState {
         //add_bullet(world);
         debug!("Init camera");
         initialize_camera(world);
+
+        self.ui_handle = Some(create_hud(world));
     }
 
     /// Activate the gameplay systems that might have been paused by other states (such as dialog)
@@ -109,60 +115,38 @@ impl State<GameData<'static, 'static>, MyEvent> for GameState {
             delete_hierarchy(handler, data.world).expect("Failed to remove WelcomeScreen");
         }
 
+        // Collision objects are removed with the entities by the collision system.
         data.world.exec(
-            |(
-                tilemap,
-                mut collisions_world,
-                entities,
-                colliders,
-                mut player,
-                bullets,
-                enemies,
-            ): (
+            |(tilemap, entities, mut player, bullets, enemies, hitboxes, pickups): (
                 Read<tilemap::Tilemap>,
-                Write<MyCollisionWorld>,
                 Entities,
-                ReadStorage<Collider>,
                 Write<PlayerResource>,
                 ReadStorage<Bullet>,
                 ReadStorage<Enemy>,
+                ReadStorage<MeleeHitbox>,
+                ReadStorage<WeaponPickup>,
             )| {
-                for e in tilemap.entities().iter().cloned() {
-                    delete_entity_with_collider(
-                        e,
-                        &colliders,
-                        &entities,
-                        &mut collisions_world.world,
-                    );
-                }
-                if let Some(e) = player.player {
-                    delete_entity_with_collider(
-                        e,
-                        &colliders,
-                        &entities,
-                        &mut collisions_world.world,
-                    );
-                }
-                player.player = None;
-
-                // remove in flight bullets
-                for (_bullet, entity) in (&bullets, &entities).join() {
-                    delete_entity_with_collider(
-                        entity,
-                        &colliders,
-                        &entities,
-                        &mut collisions_world.world,
-                    );
+                let mut to_delete = tilemap.entities().to_vec();
+                if let Some(e) = player.player.take() {
+                    to_delete.push(e);
                 }
 
-                // remove in flight enemies
-                for (_enemy, entity) in (&enemies, &entities).join() {
-                    delete_entity_with_collider(
-                        entity,
-                        &colliders,
-                        &entities,
-                        &mut collisions_world.world,
-                    );
+                // remove in flight bullets, enemies, melee attacks and dropped pickups
+                to_delete.extend((&bullets, &entities).join().map(|(_, e)| e));
+                to_delete.extend((&enemies, &entities).join().map(|(_, e)| e));
+                to_delete.extend((&hitboxes, &entities).join().map(|(_, e)| e));
+                // The pickups of the map are already with the tilemap entities.
+                let drops: Vec<_> = (&pickups, &entities)
+                    .join()
+                    .map(|(_, e)| e)
+                    .filter(|e| !to_delete.contains(e))
+                    .collect();
+                to_delete.extend(drops);
+
+                for e in to_delete {
+                    if let Err(e) = entities.delete(e) {
+                        error!("{}", e);
+                    }
                 }
             },
         );
@@ -0,0 +1,178 @@
+//! Where the player is aiming. The aim can come from the mouse cursor, the right stick of a
+//! gamepad or the keyboard (8 directions).
+use crate::{config::PlayerConfig, systems::Player};
+use amethyst::{
+    core::{
+        geometry::Plane,
+        math::{Point2, Vector2},
+        Transform,
+    },
+    ecs::{Entities, Join, Read, ReadExpect, ReadStorage, System, Write},
+    input::{InputHandler, StringBindings},
+    renderer::camera::{ActiveCamera, Camera},
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+/// Where the player is aiming. Updated by the aim system and used by the attacks.
+#[derive(Debug)]
+pub struct PlayerAim {
+    /// Normalized direction from the player.
+    pub direction: Vector2<f32>,
+}
+
+impl Default for PlayerAim {
+    fn default() -> Self {
+        Self {
+            direction: Vector2::new(0.0, -1.0),
+        }
+    }
+}
+
+/// Input used to aim.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AimSource {
+    /// Aim at the mouse cursor.
+    Mouse,
+    /// Aim with the `aim_x` and `aim_y` axes (right stick of the gamepad).
+    Gamepad,
+    /// Aim in 8 directions with the `aim_up`, `aim_down`, `aim_left` and `aim_right` actions.
+    Keyboard,
+    /// Use the gamepad or the keyboard when they are used. Aim at the mouse again once it
+    /// moves.
+    Any,
+}
+
+impl Default for AimSource {
+    fn default() -> Self {
+        AimSource::Any
+    }
+}
+
+/// Direction given by the right stick. None if the stick is in its dead zone (set in the
+/// bindings).
+fn gamepad_aim(input: &InputHandler<StringBindings>) -> Option<Vector2<f32>> {
+    let aim = Vector2::new(
+        input.axis_value("aim_x").unwrap_or(0.0),
+        input.axis_value("aim_y").unwrap_or(0.0),
+    );
+    if aim.norm() > std::f32::EPSILON {
+        Some(aim.normalize())
+    } else {
+        None
+    }
+}
+
+/// Direction given by the aim keys. None if no key is pressed.
+fn keyboard_aim(input: &InputHandler<StringBindings>) -> Option<Vector2<f32>> {
+    let is_down = |action| {
+        if input.action_is_down(action).unwrap_or(false) {
+            1.0
+        } else {
+            0.0
+        }
+    };
+    let aim = Vector2::new(
+        is_down("aim_right") - is_down("aim_left"),
+        is_down("aim_up") - is_down("aim_down"),
+    );
+    if aim.norm() > std::f32::EPSILON {
+        Some(aim.normalize())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AimSystem {
+    /// Used to detect when the mouse moves.
+    last_mouse_position: Option<(f32, f32)>,
+
+    /// The gamepad or the keyboard has been used to aim since the last time the mouse moved.
+    mouse_idle: bool,
+}
+
+impl<'s> System<'s> for AimSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        Read<'s, InputHandler<StringBindings>>,
+        // This is necessary for getting the mouse position projected on the camera view.
+        ReadStorage<'s, Camera>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, ActiveCamera>,
+        Entities<'s>,
+        Read<'s, PlayerConfig>,
+        Write<'s, PlayerAim>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            players,
+            transforms,
+            input,
+            cameras,
+            screen_dimensions,
+            active_camera,
+            entities,
+            player_config,
+            mut aim,
+        ): Self::SystemData,
+    ) {
+        let mouse_position = input.mouse_position();
+        let mouse_moved = mouse_position != self.last_mouse_position;
+        self.last_mouse_position = mouse_position;
+
+        // get the only player, transform tuple
+        if let Some((_player, transform)) = (&players, &transforms).join().next() {
+            let mouse_aim = || {
+                let (x, y) = mouse_position?;
+                let mut camera_join = (&cameras, &transforms).join();
+                let (camera, camera_transform) = active_camera
+                    .entity
+                    .and_then(|a| camera_join.get(a, &entities))
+                    .or_else(|| camera_join.next())?;
+
+                // Project a ray from the camera to the 0z axis
+                let ray = camera.projection().screen_ray(
+                    Point2::new(x, y),
+                    Vector2::new(screen_dimensions.width(), screen_dimensions.height()),
+                    camera_transform,
+                );
+                let distance = ray.intersect_plane(&Plane::with_z(0.0))?;
+                let d = ray.at_distance(distance) - transform.translation();
+                let d = Vector2::new(d.x, d.y);
+                if d.norm() > std::f32::EPSILON {
+                    Some(d.normalize())
+                } else {
+                    None
+                }
+            };
+
+            let direction = match player_config.aim_source {
+                AimSource::Mouse => mouse_aim(),
+                AimSource::Gamepad => gamepad_aim(&input),
+                AimSource::Keyboard => keyboard_aim(&input),
+                AimSource::Any => {
+                    let other_aim = gamepad_aim(&input).or_else(|| keyboard_aim(&input));
+                    if other_aim.is_some() {
+                        self.mouse_idle = true;
+                    } else if mouse_moved {
+                        self.mouse_idle = false;
+                    }
+
+                    if self.mouse_idle {
+                        other_aim
+                    } else {
+                        mouse_aim()
+                    }
+                }
+            };
+
+            if let Some(direction) = direction {
+                aim.direction = direction;
+            }
+        }
+    }
+}
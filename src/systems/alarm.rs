@@ -0,0 +1,198 @@
+//! Enemies raise the alarm when they spot the player. The alarm alerts the enemies
+//! around, rings the alarm props of the map and calls reinforcements when the alarm level
+//! is too high. The alarm level cools down over time.
+use crate::{
+    config::EnemyConfig,
+    event::{AppEvent, EventConfig},
+    states::RuntimeSystemState,
+    systems::Enemy,
+    vision::Vision,
+};
+use amethyst::{
+    core::{
+        math::{Point2, Vector2},
+        shrev::{EventChannel, ReaderId},
+        timing::Time,
+        Pausable, SystemDesc, SystemExt, Transform,
+    },
+    ecs::{
+        Component, Join, Read, ReadStorage, System, SystemData, VecStorage, World, Write,
+        WriteStorage,
+    },
+    renderer::SpriteRender,
+};
+use log::{debug, info};
+
+/// Alarm placed on the map. It rings when an enemy raises the alarm close to it and alerts
+/// all the enemies in its radius.
+#[derive(Debug, Clone, Component)]
+#[storage(VecStorage)]
+pub struct AlarmProp {
+    /// Enemies closer than that are alerted when the alarm rings.
+    pub radius: f32,
+
+    /// Event to send when the alarm starts ringing.
+    pub on_ring: Option<EventConfig>,
+
+    /// Sprite to display when the alarm is quiet.
+    pub idle_sprite: usize,
+
+    /// Sprite to display when the alarm is ringing. Same spritesheet as the idle sprite.
+    pub ringing_sprite: Option<usize>,
+
+    /// Will ring until the alarm level goes back to 0.
+    pub ringing: bool,
+}
+
+/// How loud is the alarm at the moment.
+#[derive(Debug, Default)]
+pub struct AlarmLevel {
+    pub level: f32,
+
+    /// Reinforcements are called only once until the alarm level cools down.
+    reinforcements_called: bool,
+}
+
+/// One alarm to propagate to the enemies.
+struct Alarm {
+    origin: Vector2<f32>,
+    target: Vector2<f32>,
+    radius: f32,
+    line_of_sight: bool,
+}
+
+pub struct AlarmSystem {
+    reader_id: ReaderId<AppEvent>,
+}
+
+impl AlarmSystem {
+    pub fn new(reader_id: ReaderId<AppEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+/// Build the alarm system. Like the other runtime systems, it does not run during the
+/// dialogs.
+#[derive(Debug, Default)]
+pub struct AlarmSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, Pausable<AlarmSystem, RuntimeSystemState>> for AlarmSystemDesc {
+    fn build(self, world: &mut World) -> Pausable<AlarmSystem, RuntimeSystemState> {
+        <AlarmSystem as System<'_>>::SystemData::setup(world);
+        let reader_id = world.fetch_mut::<EventChannel<AppEvent>>().register_reader();
+        AlarmSystem::new(reader_id).pausable(RuntimeSystemState::Running)
+    }
+}
+
+impl<'s> System<'s> for AlarmSystem {
+    type SystemData = (
+        WriteStorage<'s, Enemy>,
+        WriteStorage<'s, AlarmProp>,
+        WriteStorage<'s, SpriteRender>,
+        ReadStorage<'s, Transform>,
+        Write<'s, AlarmLevel>,
+        Read<'s, EnemyConfig>,
+        Read<'s, Time>,
+        Write<'s, EventChannel<AppEvent>>,
+        Vision<'s>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut enemies,
+            mut alarm_props,
+            mut sprites,
+            transforms,
+            mut alarm_level,
+            enemy_config,
+            time,
+            mut events,
+            vision,
+        ): Self::SystemData,
+    ) {
+        let config = enemy_config.alarm;
+        let mut alarms = vec![];
+        for ev in events.read(&mut self.reader_id) {
+            if let AppEvent::Alarm { origin, target } = ev {
+                alarms.push(Alarm {
+                    origin: *origin,
+                    target: *target,
+                    radius: config.radius,
+                    line_of_sight: config.line_of_sight,
+                });
+            }
+        }
+
+        let mut events_to_send = vec![];
+        alarm_level.level = (alarm_level.level + alarms.len() as f32 * config.level_increase)
+            .min(config.max_level);
+
+        // Alarm props that start ringing will alert more enemies so they are added to the
+        // alarms to propagate.
+        while let Some(alarm) = alarms.pop() {
+            debug!("Propagate alarm from {:?}", alarm.origin);
+            for (enemy, t) in (&mut enemies, &transforms).join() {
+                let position = t.translation().xy();
+                if (position - alarm.origin).norm() > alarm.radius {
+                    continue;
+                }
+
+                if alarm.line_of_sight
+                    && !vision.line_of_sight(Point2::from(alarm.origin), Point2::from(position))
+                {
+                    continue;
+                }
+
+                enemy.alert(alarm.target);
+            }
+
+            for (prop, t, sprite) in (&mut alarm_props, &transforms, (&mut sprites).maybe()).join()
+            {
+                let position = t.translation().xy();
+                if prop.ringing || (position - alarm.origin).norm() > alarm.radius {
+                    continue;
+                }
+
+                info!("Alarm prop starts ringing");
+                prop.ringing = true;
+                if let (Some(sprite_number), Some(sprite)) = (prop.ringing_sprite, sprite) {
+                    sprite.sprite_number = sprite_number;
+                }
+                if let Some(ref ev) = prop.on_ring {
+                    events_to_send.push(ev.to_event());
+                }
+
+                alarms.push(Alarm {
+                    origin: position,
+                    target: alarm.target,
+                    radius: prop.radius,
+                    line_of_sight: false,
+                });
+            }
+        }
+
+        if alarm_level.level >= config.max_level && !alarm_level.reinforcements_called {
+            info!("Alarm level is at maximum. Call reinforcements");
+            alarm_level.reinforcements_called = true;
+            events_to_send.push(AppEvent::Reinforcements(config.reinforcements));
+        }
+
+        // Cool down.
+        if alarm_level.level > 0.0 {
+            alarm_level.level -= config.cooldown * time.delta_seconds();
+            if alarm_level.level <= 0.0 {
+                info!("Alarm is over");
+                *alarm_level = AlarmLevel::default();
+                for (prop, sprite) in (&mut alarm_props, (&mut sprites).maybe()).join() {
+                    prop.ringing = false;
+                    if let Some(sprite) = sprite {
+                        sprite.sprite_number = prop.idle_sprite;
+                    }
+                }
+            }
+        }
+
+        events.drain_vec_write(&mut events_to_send);
+    }
+}
@@ -1,44 +1,220 @@
 //! Systems to manage the player attacks...
-//! Player will be looking in the direction of the mouse. It will attack on mouse click
+//! Player will be shooting in the direction given by the aim module. It will attack
+//! with the weapon currently equipped when the `attack` action is down. Weapons are described
+//! in weapons.ron. The close combat attack is in the melee module.
 
 use amethyst::{
     core::{
-        geometry::Plane,
-        math::{Point2, Vector2},
+        math::{Rotation2, Vector2},
         timing::Time,
         SystemDesc, Transform,
     },
     derive::SystemDesc,
     ecs::{
-        Component, Entities, Join, LazyUpdate, Read, ReadExpect, ReadStorage, System, SystemData,
-        VecStorage, World, Write, WriteStorage,
+        Component, Entities, Join, LazyUpdate, Read, ReadStorage, System, SystemData, VecStorage,
+        World, Write, WriteStorage,
     },
     input::{InputHandler, StringBindings},
-    renderer::camera::{ActiveCamera, Camera},
-    window::ScreenDimensions,
-    winit::MouseButton,
 };
 
-use crate::systems::{BulletSpawner, MyCollisionWorld, Player};
+use crate::{
+    config::{PlayerConfig, WeaponArchetype, WeaponConfig},
+    error::{GameError, GameResult},
+    systems::{
+        aim::PlayerAim, visibility::PlayerVisibility, BulletSpawner, MyCollisionWorld, Player,
+        PlayerStatus,
+    },
+};
 
-use log::{debug, error};
+use log::{debug, error, info};
 #[derive(Debug, Default, SystemDesc)]
 pub struct AttackSystem;
 
-#[derive(Debug, Clone, Copy, Component)]
-#[storage(VecStorage)]
+/// One weapon of the inventory.
+#[derive(Debug, Clone)]
 pub struct Weapon {
-    /// Time before the enemy can shoot.
+    /// Name of the weapon in weapons.ron
+    pub name: String,
+
+    config: WeaponArchetype,
+
+    /// Time before the weapon can shoot.
     time_before_shooting: f32,
-    /// Number of sec the enemy will wait before shooting next.
-    reload_time: f32,
+
+    /// Time before the magazine is full again. None when the weapon is not reloading.
+    reloading: Option<f32>,
+
+    /// Shots left in the magazine.
+    magazine: u32,
+
+    /// Shots left outside of the magazine. None is infinite.
+    ammo: Option<u32>,
 }
 
-impl Default for Weapon {
-    fn default() -> Self {
+impl Weapon {
+    pub fn new(name: &str, config: &WeaponArchetype) -> Self {
         Self {
+            name: name.to_string(),
+            config: config.clone(),
             time_before_shooting: 0.0,
-            reload_time: 1.0,
+            reloading: None,
+            magazine: config.magazine_size.unwrap_or(0),
+            ammo: config.ammo,
+        }
+    }
+
+    /// Name of the bullet fired by the weapon.
+    pub fn bullet(&self) -> &str {
+        &self.config.bullet
+    }
+
+    /// Update the timers of the weapon.
+    pub fn update(&mut self, dt: f32) {
+        self.time_before_shooting = 0.0f32.max(self.time_before_shooting - dt);
+        if let Some(reloading) = self.reloading {
+            if reloading - dt <= 0.0 {
+                self.fill_magazine();
+            } else {
+                self.reloading = Some(reloading - dt);
+            }
+        }
+    }
+
+    pub fn can_shoot(&self) -> bool {
+        self.time_before_shooting <= 0.0
+            && self.reloading.is_none()
+            && (self.config.magazine_size.is_none() || self.magazine > 0)
+    }
+
+    /// Consume one shot. The weapon starts reloading when the magazine is empty.
+    pub fn shoot(&mut self) {
+        if self.config.fire_rate > 0.0 {
+            self.time_before_shooting = 1.0 / self.config.fire_rate;
+        }
+
+        if self.config.magazine_size.is_some() {
+            self.magazine = self.magazine.saturating_sub(1);
+            if self.magazine == 0 {
+                self.reload();
+            }
+        }
+    }
+
+    /// Start reloading if the magazine is not full and there is some ammo left.
+    pub fn reload(&mut self) {
+        if let Some(magazine_size) = self.config.magazine_size {
+            let has_ammo = self.ammo.map_or(true, |ammo| ammo > 0);
+            if self.reloading.is_none() && has_ammo && self.magazine < magazine_size {
+                debug!("Reload {}", self.name);
+                self.reloading = Some(self.config.reload_time);
+            }
+        }
+    }
+
+    /// Add the ammo of a new weapon of the same kind.
+    pub fn add_ammo(&mut self, ammo: Option<u32>) {
+        self.ammo = match (self.ammo, ammo) {
+            (Some(current), Some(ammo)) => Some(current + ammo),
+            _ => None,
+        };
+        if self.magazine == 0 {
+            self.reload();
+        }
+    }
+
+    /// Directions of the bullets of one shot, spread around the aiming direction.
+    pub fn directions(&self, aim: Vector2<f32>) -> Vec<Vector2<f32>> {
+        let projectiles = self.config.projectiles.max(1);
+        if projectiles == 1 {
+            return vec![aim];
+        }
+
+        let spread = self.config.spread;
+        let step = spread / (projectiles - 1) as f32;
+        (0..projectiles)
+            .map(|i| Rotation2::new(-spread / 2.0 + i as f32 * step) * aim)
+            .collect()
+    }
+
+    fn fill_magazine(&mut self) {
+        self.reloading = None;
+        if let Some(magazine_size) = self.config.magazine_size {
+            let needed = magazine_size - self.magazine;
+            let taken = self.ammo.map_or(needed, |ammo| ammo.min(needed));
+            self.ammo = self.ammo.map(|ammo| ammo - taken);
+            self.magazine += taken;
+        }
+    }
+}
+
+/// Weapons carried by the player. Only one of them is equipped.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(VecStorage)]
+pub struct Inventory {
+    weapons: Vec<Weapon>,
+
+    /// Index of the equipped weapon.
+    current: usize,
+
+    /// A switch button is held so the weapon should not change again.
+    switch_held: bool,
+}
+
+impl Inventory {
+    /// Create the inventory with the starting weapons. Fails if a weapon does not exist.
+    pub fn from_config(config: &WeaponConfig) -> GameResult<Self> {
+        let mut inventory = Self::default();
+        for name in &config.starting_weapons {
+            let weapon = config
+                .weapons
+                .get(name)
+                .ok_or_else(|| GameError::WeaponNotFound { name: name.clone() })?;
+            inventory.weapons.push(Weapon::new(name, weapon));
+        }
+        Ok(inventory)
+    }
+
+    pub fn current_weapon(&self) -> Option<&Weapon> {
+        self.weapons.get(self.current)
+    }
+
+    pub fn current_weapon_mut(&mut self) -> Option<&mut Weapon> {
+        self.weapons.get_mut(self.current)
+    }
+
+    /// Update the timers of all the weapons.
+    pub fn update(&mut self, dt: f32) {
+        for weapon in &mut self.weapons {
+            weapon.update(dt);
+        }
+    }
+
+    /// Switch to the next weapon when `next` is pressed, or to the previous one when
+    /// `previous` is pressed. Holding the button does not switch again.
+    pub fn switch(&mut self, next: bool, previous: bool) {
+        if !self.switch_held && !self.weapons.is_empty() {
+            let nb_weapons = self.weapons.len();
+            if next {
+                self.current = (self.current + 1) % nb_weapons;
+            } else if previous {
+                self.current = (self.current + nb_weapons - 1) % nb_weapons;
+            }
+
+            if next || previous {
+                info!("Switch to weapon {:?}", self.current_weapon().map(|w| &w.name));
+            }
+        }
+        self.switch_held = next || previous;
+    }
+
+    /// Add a new weapon and equip it. If the weapon is already in the inventory, its
+    /// ammo is added instead.
+    pub fn add_weapon(&mut self, name: &str, config: &WeaponArchetype) {
+        if let Some(weapon) = self.weapons.iter_mut().find(|w| w.name == name) {
+            weapon.add_ammo(config.ammo);
+        } else {
+            self.weapons.push(Weapon::new(name, config));
+            self.current = self.weapons.len() - 1;
         }
     }
 }
@@ -47,18 +223,18 @@ impl<'s> System<'s> for AttackSystem {
     type SystemData = (
         ReadStorage<'s, Player>,
         ReadStorage<'s, Transform>,
-        WriteStorage<'s, Weapon>,
+        WriteStorage<'s, Inventory>,
         Read<'s, InputHandler<StringBindings>>,
         Read<'s, Time>,
-        // This is necessary for getting the mouse position projected on the camera view.
-        ReadStorage<'s, Camera>,
-        ReadExpect<'s, ScreenDimensions>,
-        Read<'s, ActiveCamera>,
+        Read<'s, PlayerAim>,
         // This is necessary to spawn bullets.
         Entities<'s>,
         Read<'s, LazyUpdate>,
         Read<'s, BulletSpawner>,
         Write<'s, MyCollisionWorld>,
+        // Shooting makes noise.
+        Write<'s, PlayerVisibility>,
+        Read<'s, PlayerConfig>,
     );
 
     fn run(
@@ -66,66 +242,168 @@ impl<'s> System<'s> for AttackSystem {
         (
             players,
             transforms,
-            mut weapon,
+            mut inventories,
             input,
             time,
-            cameras,
-            screen_dimensions,
-            active_camera,
+            aim,
             entities,
             updater,
             bullet_spawner,
             mut collision_world,
+            mut visibility,
+            player_config,
         ): Self::SystemData,
     ) {
         // get the only player, transform tuple
-        if let Some((_player, transform, weapon)) =
-            (&players, &transforms, &mut weapon).join().next()
+        if let Some((player, transform, inventory)) =
+            (&players, &transforms, &mut inventories).join().next()
         {
             // update time before shooting.
-            weapon.time_before_shooting =
-                0.0f32.max(weapon.time_before_shooting - time.delta_seconds());
-
-            if input.mouse_button_is_down(MouseButton::Left) {
-                if let Some((x, y)) = input.mouse_position() {
-                    let mut camera_join = (&cameras, &transforms).join();
-                    if let Some((camera, camera_transform)) = active_camera
-                        .entity
-                        .and_then(|a| camera_join.get(a, &entities))
-                        .or_else(|| camera_join.next())
-                    {
-                        // Project a ray from the camera to the 0z axis
-                        let ray = camera.projection().screen_ray(
-                            Point2::new(x, y),
-                            Vector2::new(screen_dimensions.width(), screen_dimensions.height()),
-                            camera_transform,
-                        );
-                        let distance = ray.intersect_plane(&Plane::with_z(0.0)).unwrap();
-                        let mouse_world_position = ray.at_distance(distance);
-                        debug!(
-                            "Mouse click at {:?} (player is at {:?}",
-                            mouse_world_position,
-                            transform.translation()
-                        );
-                        if weapon.time_before_shooting <= 0.0 {
-                            let d = mouse_world_position - transform.translation();
-                            let d = Vector2::new(d.x, d.y).normalize();
+            inventory.update(time.delta_seconds());
+            inventory.switch(
+                input.action_is_down("next_weapon").unwrap_or(false),
+                input.action_is_down("previous_weapon").unwrap_or(false),
+            );
+
+            // No shooting during a dash or a fall.
+            let can_attack = match player.state {
+                PlayerStatus::Walking => true,
+                _ => false,
+            };
+            if can_attack && input.action_is_down("attack").unwrap_or(false) {
+                debug!(
+                    "Attack towards {:?} (player is at {:?}",
+                    aim.direction,
+                    transform.translation()
+                );
+                if let Some(weapon) = inventory.current_weapon_mut() {
+                    if weapon.can_shoot() {
+                        for direction in weapon.directions(aim.direction) {
                             if let Err(e) = bullet_spawner.spawn_player_bullet(
                                 &entities,
                                 &updater,
                                 &mut collision_world,
-                                0,
+                                weapon.bullet(),
                                 *transform.translation(),
-                                d,
-                                100.0,
+                                direction,
                             ) {
                                 error!("Error while spawning player bullet = {:?}", e);
                             }
-                            weapon.time_before_shooting = weapon.reload_time;
                         }
+                        weapon.shoot();
+                        visibility.add_noise(player_config.visibility.shoot_noise);
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archetype(magazine_size: Option<u32>, ammo: Option<u32>) -> WeaponArchetype {
+        WeaponArchetype {
+            bullet: "bullet".to_string(),
+            fire_rate: 0.0,
+            projectiles: 1,
+            spread: 0.0,
+            magazine_size,
+            reload_time: 1.0,
+            ammo,
+        }
+    }
+
+    #[test]
+    fn empty_magazine_starts_reloading() {
+        let mut weapon = Weapon::new("gun", &archetype(Some(2), None));
+        weapon.shoot();
+        assert!(weapon.can_shoot());
+        weapon.shoot();
+        assert_eq!(weapon.magazine, 0);
+        assert_eq!(weapon.reloading, Some(1.0));
+        assert!(!weapon.can_shoot());
+    }
+
+    #[test]
+    fn magazine_is_full_after_the_reload_time() {
+        let mut weapon = Weapon::new("gun", &archetype(Some(2), None));
+        weapon.shoot();
+        weapon.shoot();
+
+        weapon.update(0.6);
+        assert!(!weapon.can_shoot());
+        weapon.update(0.6);
+        assert!(weapon.can_shoot());
+        assert_eq!(weapon.magazine, 2);
+        assert_eq!(weapon.reloading, None);
+    }
+
+    #[test]
+    fn reload_takes_what_is_left_in_the_reserve() {
+        let mut weapon = Weapon::new("gun", &archetype(Some(3), Some(2)));
+        for _ in 0..3 {
+            weapon.shoot();
+        }
+        weapon.update(1.0);
+        assert_eq!(weapon.magazine, 2);
+        assert_eq!(weapon.ammo, Some(0));
+
+        weapon.shoot();
+        weapon.shoot();
+        assert_eq!(weapon.reloading, None);
+        assert!(!weapon.can_shoot());
+    }
+
+    #[test]
+    fn picking_ammo_reloads_an_empty_weapon() {
+        let mut weapon = Weapon::new("gun", &archetype(Some(1), Some(0)));
+        weapon.shoot();
+        assert!(!weapon.can_shoot());
+
+        weapon.add_ammo(Some(5));
+        weapon.update(1.0);
+        assert_eq!(weapon.magazine, 1);
+        assert_eq!(weapon.ammo, Some(4));
+    }
+
+    #[test]
+    fn weapon_without_magazine_never_reloads() {
+        let mut weapon = Weapon::new("gun", &archetype(None, None));
+        for _ in 0..10 {
+            weapon.shoot();
+        }
+        assert!(weapon.can_shoot());
+        assert_eq!(weapon.reloading, None);
+    }
+
+    #[test]
+    fn switch_wraps_around_and_ignores_held_button() {
+        let mut inventory = Inventory::default();
+        inventory.add_weapon("a", &archetype(None, None));
+        inventory.add_weapon("b", &archetype(None, None));
+        inventory.add_weapon("c", &archetype(None, None));
+        assert_eq!(inventory.current, 2);
+
+        inventory.switch(true, false);
+        assert_eq!(inventory.current, 0);
+        inventory.switch(true, false);
+        assert_eq!(inventory.current, 0);
+
+        inventory.switch(false, false);
+        inventory.switch(false, true);
+        assert_eq!(inventory.current, 2);
+    }
+
+    #[test]
+    fn picking_a_known_weapon_adds_its_ammo() {
+        let mut inventory = Inventory::default();
+        inventory.add_weapon("a", &archetype(Some(1), Some(1)));
+        inventory.add_weapon("b", &archetype(None, None));
+        inventory.add_weapon("a", &archetype(Some(1), Some(3)));
+        assert_eq!(inventory.weapons.len(), 2);
+        assert_eq!(inventory.current_weapon().map(|w| w.name.as_str()), Some("b"));
+        assert_eq!(inventory.weapons[0].ammo, Some(4));
+    }
+}
@@ -2,9 +2,12 @@
 //! to check whether they hit anything.
 //!
 use crate::{
-    config::BulletConfig,
+    config::{degrees, BulletConfig, EnemyArchetypeConfig, PatternConfig, WeaponConfig},
     error::{GameError, GameResult},
-    systems::{Collider, ColliderObjectType, MyCollisionWorld},
+    systems::{
+        health::Damage, Collider, ColliderObjectType, Enemy, MyCollisionWorld, PlayerResource,
+    },
+    tilemap::Tilemap,
     util::load_spritesheet,
 };
 
@@ -17,18 +20,43 @@ use amethyst::{
     },
     derive::SystemDesc,
     ecs::{
-        Component, DenseVecStorage, Entities, Join, LazyUpdate, Read, System, SystemData, World,
-        WriteStorage,
+        Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System,
+        SystemData, World, Write, WriteStorage,
     },
     prelude::*,
     renderer::{SpriteRender, SpriteSheet},
 };
 
 #[allow(unused_imports)]
-use log::{debug, trace};
+use log::{debug, error, trace};
 use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Who fired the bullet. Bullets only hit the other side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BulletSide {
+    Player,
+    Enemy,
+}
+
+impl Default for BulletSide {
+    fn default() -> Self {
+        BulletSide::Enemy
+    }
+}
+
+impl BulletSide {
+    /// Collider types that the bullet can hit.
+    fn collide_with(self) -> &'static [ColliderObjectType] {
+        match self {
+            BulletSide::Player => &[ColliderObjectType::Enemy, ColliderObjectType::Wall],
+            BulletSide::Enemy => &[ColliderObjectType::Player, ColliderObjectType::Wall],
+        }
+    }
+}
 
-/// A basic bullet. Goes straight in a line with a given speed.
+/// A bullet flies with a given speed in a direction. Its behavior will modify the speed and
+/// direction over time.
 #[derive(Debug, Clone, Component, Serialize, Deserialize)]
 #[storage(DenseVecStorage)]
 pub struct Bullet {
@@ -38,49 +66,196 @@ pub struct Bullet {
     /// Where it is headed,
     pub direction: Vector2<f32>,
 
-    /// Bullet path (isometry applied to the direction at each update, so make sure it is small :D)
-    pub path_mod: Option<Rotation2<f32>>,
+    /// How the bullet moves.
+    #[serde(default)]
+    pub behavior: BulletBehavior,
+
+    /// Time since the bullet was fired.
+    #[serde(default)]
+    pub elapsed: f32,
+
+    /// The bullet disappears after that many seconds.
+    #[serde(default)]
+    pub lifetime: Option<f32>,
+
+    /// Distance flown since the bullet was fired.
+    #[serde(default)]
+    pub distance: f32,
+
+    /// The bullet disappears after flying that distance.
+    #[serde(default)]
+    pub max_range: Option<f32>,
+
+    /// Damage dealt to the entity that is hit.
+    #[serde(default)]
+    pub damage: Damage,
+
+    /// Entity followed by homing bullets.
+    #[serde(skip)]
+    pub target: Option<Entity>,
+
+    #[serde(default)]
+    pub side: BulletSide,
+
+    /// Name of the bullet archetype. Used when the bullet splits.
+    #[serde(default)]
+    pub name: String,
+
+    /// How many times the bullet can still bounce off a wall.
+    #[serde(default)]
+    pub bounces_left: u32,
+
+    /// How many more entities the bullet can go through.
+    #[serde(default)]
+    pub pierce_left: u32,
+
+    /// Speed given to the entity that is hit, in unit/sec.
+    #[serde(default)]
+    pub knockback: f32,
+
+    /// Entities already hit by the bullet. A piercing bullet hits each entity only once.
+    #[serde(skip)]
+    pub hit_entities: Vec<Entity>,
 }
 
 /// Define behavior of a bullet.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum BulletBehavior {
     /// Bullet that goes straight
-    Simple { speed: f32, direction: Vector2<f32> },
+    Straight,
 
-    /// Bullet that rotations with a path
+    /// Bullet that rotates its direction (radian per second)
     Rotating {
-        speed: f32,
-        direction: Vector2<f32>,
-        rotation: Rotation2<f32>,
+        #[serde(rename = "rot_per_sec_deg", with = "degrees")]
+        rot_per_sec: f32,
+    },
+
+    /// Bullet that turns towards its target (radian per second). Enemy bullets follow the
+    /// player and player bullets follow the closest enemy.
+    Homing {
+        #[serde(rename = "turn_speed_deg", with = "degrees")]
+        turn_speed: f32,
     },
+
+    /// Bullet that oscillates around its direction.
+    SineWave { amplitude: f32, frequency: f32 },
+
+    /// Bullet that goes faster and faster (unit/sec^2) until it reaches max_speed.
+    Accelerating { acceleration: f32, max_speed: f32 },
+
+    /// Bullet that splits in `count` straight bullets after some time. The new bullets are
+    /// spread over `spread` radians around the direction of the bullet.
+    Split {
+        after: f32,
+        count: usize,
+        #[serde(rename = "spread_deg", with = "degrees")]
+        spread: f32,
+    },
+}
+
+impl Default for BulletBehavior {
+    fn default() -> Self {
+        BulletBehavior::Straight
+    }
 }
 
-impl BulletBehavior {
-    /// What to apply at each frame.
-    pub fn apply(&mut self, bullet_transform: &mut Transform, time_delta: f32) {
-        match *self {
-            BulletBehavior::Simple {
-                ref speed,
-                ref direction,
+impl Bullet {
+    /// Move the bullet. `target_position` is the position of the target of homing bullets.
+    ///
+    /// Return true if the bullet should split.
+    pub fn update(
+        &mut self,
+        bullet_transform: &mut Transform,
+        target_position: Option<Vector2<f32>>,
+        time_delta: f32,
+    ) -> bool {
+        let previous_elapsed = self.elapsed;
+        self.elapsed += time_delta;
+
+        let mut delta_mvt = self.direction * self.speed * time_delta;
+        let mut should_split = false;
+        match self.behavior {
+            BulletBehavior::Straight => (),
+            BulletBehavior::Rotating { rot_per_sec } => {
+                self.direction = Rotation2::new(rot_per_sec * time_delta) * self.direction;
+            }
+            BulletBehavior::Homing { turn_speed } => {
+                if let Some(target_position) = target_position {
+                    let to_target = target_position - bullet_transform.translation().xy();
+                    if to_target.norm() > std::f32::EPSILON {
+                        // Turn towards the target, but not more than the turn speed.
+                        let angle = Rotation2::rotation_between(&self.direction, &to_target)
+                            .angle()
+                            .max(-turn_speed * time_delta)
+                            .min(turn_speed * time_delta);
+                        self.direction = Rotation2::new(angle) * self.direction;
+                    }
+                }
+            }
+            BulletBehavior::SineWave {
+                amplitude,
+                frequency,
             } => {
-                let delta_mvt = *direction * *speed * time_delta;
-                bullet_transform.prepend_translation_x(delta_mvt.x);
-                bullet_transform.prepend_translation_y(delta_mvt.y);
+                // Lateral offset is amplitude * sin(2*pi*f*t). Only the change of offset since
+                // the last frame is applied.
+                let pulsation = 2.0 * std::f32::consts::PI * frequency;
+                let lateral = amplitude
+                    * ((pulsation * self.elapsed).sin() - (pulsation * previous_elapsed).sin());
+                let normal = Vector2::new(-self.direction.y, self.direction.x);
+                delta_mvt += normal * lateral;
             }
-            BulletBehavior::Rotating {
-                ref speed,
-                ref mut direction,
-                ref rotation,
+            BulletBehavior::Accelerating {
+                acceleration,
+                max_speed,
             } => {
-                let delta_mvt = *direction * *speed * time_delta;
-                bullet_transform.prepend_translation_x(delta_mvt.x);
-                bullet_transform.prepend_translation_y(delta_mvt.y);
-                *direction = *rotation * *direction;
+                self.speed = (self.speed + acceleration * time_delta).min(max_speed);
+            }
+            BulletBehavior::Split { after, .. } => {
+                should_split = previous_elapsed < after && self.elapsed >= after;
             }
         }
+
+        bullet_transform.prepend_translation_x(delta_mvt.x);
+        bullet_transform.prepend_translation_y(delta_mvt.y);
+        self.distance += delta_mvt.norm();
+        should_split
+    }
+
+    /// Return true if the bullet has been flying for longer than its lifetime or its range.
+    pub fn is_expired(&self) -> bool {
+        let too_old = self
+            .lifetime
+            .map(|lifetime| self.elapsed >= lifetime)
+            .unwrap_or(false);
+        let too_far = self
+            .max_range
+            .map(|max_range| self.distance >= max_range)
+            .unwrap_or(false);
+        too_old || too_far
+    }
+
+    /// Directions of the bullets created when this bullet splits.
+    fn split_directions(&self) -> Vec<Vector2<f32>> {
+        if let BulletBehavior::Split { count, spread, .. } = self.behavior {
+            (0..count)
+                .map(|i| {
+                    let angle = if count > 1 {
+                        -spread / 2.0 + spread * i as f32 / (count - 1) as f32
+                    } else {
+                        0.0
+                    };
+                    Rotation2::new(angle) * self.direction
+                })
+                .collect()
+        } else {
+            vec![]
+        }
     }
 }
 
+/// Bullets further than that from the map are removed.
+const OUT_OF_MAP_MARGIN: f32 = 32.0;
+
 #[derive(SystemDesc)]
 pub struct BulletSystem;
 
@@ -88,161 +263,305 @@ impl<'s> System<'s> for BulletSystem {
     type SystemData = (
         WriteStorage<'s, Transform>,
         WriteStorage<'s, Bullet>,
+        ReadStorage<'s, Enemy>,
+        Read<'s, PlayerResource>,
         Read<'s, Time>,
+        Read<'s, Tilemap>,
+        // To split the bullets.
+        Entities<'s>,
+        Read<'s, LazyUpdate>,
+        Read<'s, BulletSpawner>,
+        Write<'s, MyCollisionWorld>,
     );
 
-    fn run(&mut self, (mut transforms, mut bullets, time): Self::SystemData) {
-        for (bullet, t) in (&mut bullets, &mut transforms).join() {
-            let delta_mvt = bullet.direction * bullet.speed * time.delta_seconds();
-            //trace!("Will move bullet by {:?}", delta_mvt);
+    fn run(
+        &mut self,
+        (
+            mut transforms,
+            mut bullets,
+            enemies,
+            player,
+            time,
+            tilemap,
+            entities,
+            updater,
+            bullet_spawner,
+            mut collision,
+        ): Self::SystemData,
+    ) {
+        // Possible targets of homing bullets.
+        let player_target = player
+            .player
+            .and_then(|e| transforms.get(e).map(|t| (e, t.translation().xy())));
+        let enemy_targets: Vec<_> = (&enemies, &transforms, &entities)
+            .join()
+            .map(|(_, t, e)| (e, t.translation().xy()))
+            .collect();
+
+        let mut to_split = vec![];
+        let mut to_remove = vec![];
+        for (bullet, t, e) in (&mut bullets, &mut transforms, &entities).join() {
+            let target_position = if let BulletBehavior::Homing { .. } = bullet.behavior {
+                find_target(bullet, t, player_target, &enemy_targets)
+            } else {
+                None
+            };
+
+            if bullet.update(t, target_position, time.delta_seconds()) {
+                to_split.push((e, bullet.clone(), *t.translation()));
+            } else if bullet.is_expired()
+                || !tilemap.is_inside(t.translation().xy(), OUT_OF_MAP_MARGIN)
+            {
+                to_remove.push(e);
+            }
+        }
 
-            // alternative, could set the transform rotation to face the shooting direction.
-            // and use the move along method
-            t.prepend_translation_x(delta_mvt.x);
-            t.prepend_translation_y(delta_mvt.y);
+        for (e, bullet, origin) in to_split {
+            for direction in bullet.split_directions() {
+                if let Err(err) = bullet_spawner.spawn_bullet(
+                    &entities,
+                    &updater,
+                    &mut collision,
+                    &bullet.name,
+                    origin,
+                    direction,
+                    bullet.side,
+                    Some(bullet.speed),
+                    Some(BulletBehavior::Straight),
+                ) {
+                    error!("Cannot split bullet: {}", err);
+                }
+            }
+            to_remove.push(e);
+        }
 
-            // If we have some rotation to the bullet (yeah that is realistic), we need to change
-            // the direction know.
-            if let Some(modification) = bullet.path_mod {
-                bullet.direction = modification * bullet.direction;
+        // The collision system removes the collision objects of the deleted entities.
+        for e in to_remove {
+            if let Err(e) = entities.delete(e) {
+                error!("{}", e);
             }
         }
     }
 }
 
+/// Find the position of the target of an homing bullet. If the bullet does not have a target
+/// yet, enemy bullets will choose the player and player bullets the closest enemy.
+fn find_target(
+    bullet: &mut Bullet,
+    t: &Transform,
+    player_target: Option<(Entity, Vector2<f32>)>,
+    enemy_targets: &[(Entity, Vector2<f32>)],
+) -> Option<Vector2<f32>> {
+    let position = t.translation().xy();
+    let current_target = match bullet.side {
+        BulletSide::Enemy => player_target.filter(|(e, _)| Some(*e) == bullet.target),
+        BulletSide::Player => enemy_targets
+            .iter()
+            .cloned()
+            .find(|(e, _)| Some(*e) == bullet.target),
+    };
+
+    let target = current_target.or_else(|| match bullet.side {
+        BulletSide::Enemy => player_target,
+        BulletSide::Player => enemy_targets.iter().cloned().min_by(|(_, a), (_, b)| {
+            (a - position)
+                .norm()
+                .partial_cmp(&(b - position).norm())
+                .unwrap_or(Ordering::Equal)
+        }),
+    });
+
+    bullet.target = target.map(|(e, _)| e);
+    target.map(|(_, target_position)| target_position)
+}
+
 // ==================================================================
 // Keep the bullet texture handles in the world's resources. This is
 // setup at on_start of the game state
 // ==================================================================
 
-pub const SIMPLE_BULLET_IDX: usize = 0;
-
-/// Used as a resource to spawn new bullets from a bullet ID and a direction and speed.
+/// Used as a resource to spawn new bullets from a bullet name and a direction.
 #[derive(Debug, Default)]
 pub struct BulletSpawner {
-    textures: Vec<Handle<SpriteSheet>>,
+    textures: HashMap<String, Handle<SpriteSheet>>,
     bullet_config: BulletConfig,
 }
 
 impl BulletSpawner {
     /// Will load all the textures of bullet in memory (actually
     /// stores handles) :)
-    pub fn init(world: &mut World) -> Self {
-        let handle = load_spritesheet("bullet", world);
-        let textures = vec![handle];
-        Self {
-            textures,
-            bullet_config: *world.read_resource::<BulletConfig>(),
+    ///
+    /// Fails if a bullet used in the configuration files does not exist.
+    pub fn init(world: &mut World) -> GameResult<Self> {
+        let bullet_config = world.read_resource::<BulletConfig>().clone();
+
+        let mut used_bullets: Vec<String> = world
+            .read_resource::<EnemyArchetypeConfig>()
+            .bullet_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        used_bullets.extend(
+            world
+                .read_resource::<WeaponConfig>()
+                .bullet_names()
+                .iter()
+                .map(|name| name.to_string()),
+        );
+        used_bullets.extend(
+            world
+                .read_resource::<PatternConfig>()
+                .bullet_names()
+                .iter()
+                .map(|name| name.to_string()),
+        );
+        if let Some(name) = used_bullets
+            .into_iter()
+            .find(|name| !bullet_config.bullets.contains_key(name))
+        {
+            return Err(GameError::BulletNotFound { name });
         }
+
+        let mut textures = HashMap::new();
+        for archetype in bullet_config.bullets.values() {
+            if !textures.contains_key(&archetype.sprite_sheet) {
+                let handle = load_spritesheet(&archetype.sprite_sheet, world);
+                textures.insert(archetype.sprite_sheet.clone(), handle);
+            }
+        }
+
+        Ok(Self {
+            textures,
+            bullet_config,
+        })
     }
 
     /// Spawn a new bullet.
     /// Entities will create a new entity. Updater will add components to the entity.
     ///
-    /// bullet_idx should be found as a constant usize in this module.
+    /// name is the name of the bullet in bullets.ron
     pub fn spawn_enemy_bullet(
         &self,
         entities: &Entities,
         updater: &LazyUpdate,
         collision: &mut MyCollisionWorld,
-        bullet_idx: usize,
+        name: &str,
         origin: Vector3<f32>,
         direction: Vector2<f32>,
-        speed: f32,
     ) -> GameResult<()> {
         self.spawn_bullet(
             entities,
             updater,
             collision,
-            bullet_idx,
+            name,
             origin,
             direction,
-            speed,
-            &[ColliderObjectType::Player, ColliderObjectType::Wall],
+            BulletSide::Enemy,
+            None,
+            None,
         )
     }
+
     pub fn spawn_player_bullet(
         &self,
         entities: &Entities,
         updater: &LazyUpdate,
         collision: &mut MyCollisionWorld,
-        bullet_idx: usize,
+        name: &str,
         origin: Vector3<f32>,
         direction: Vector2<f32>,
-        speed: f32,
     ) -> GameResult<()> {
         self.spawn_bullet(
             entities,
             updater,
             collision,
-            bullet_idx,
+            name,
             origin,
             direction,
-            speed,
-            &[ColliderObjectType::Enemy, ColliderObjectType::Wall],
+            BulletSide::Player,
+            None,
+            None,
         )
     }
 
-    fn spawn_bullet(
+    /// Spawn a bullet. The speed and behavior of the bullet archetype can be
+    /// overridden.
+    pub fn spawn_bullet(
         &self,
         entities: &Entities,
         updater: &LazyUpdate,
         collision: &mut MyCollisionWorld,
-        bullet_idx: usize,
+        name: &str,
         origin: Vector3<f32>,
         direction: Vector2<f32>,
-        speed: f32,
-        collide_with: &[ColliderObjectType],
+        side: BulletSide,
+        speed: Option<f32>,
+        behavior: Option<BulletBehavior>,
     ) -> GameResult<()> {
+        let archetype = self
+            .bullet_config
+            .bullets
+            .get(name)
+            .ok_or_else(|| GameError::BulletNotFound {
+                name: name.to_string(),
+            })?;
+        let handle = self.textures.get(&archetype.sprite_sheet).ok_or_else(|| {
+            GameError::BulletNotFound {
+                name: name.to_string(),
+            }
+        })?;
+        let speed = speed.unwrap_or(archetype.speed);
         debug!(
             "Will spawn bullet {} from point {:?} with direction {:?} at speed {}",
-            bullet_idx, origin, direction, speed
+            name, origin, direction, speed
         );
 
-        if let Some(handle) = self.textures.get(bullet_idx) {
-            let bullet = entities.create();
-
-            let collider = {
-                let collider = Collider::new_rect(
-                    Vector2::new(0.0, 0.0),
-                    8.0,
-                    8.0,
-                    &mut collision.world,
-                    ColliderObjectType::Bullet,
-                    Some(collide_with),
-                    Some(bullet),
-                );
-                //collider.set_entddity(&mut collision.world, bullet);
-                collider
-            };
+        let bullet = entities.create();
 
-            let mut t = Transform::default();
-            // don't want the bullet to fly to fast :D
-            let direction = direction.normalize();
-            t.prepend_translation(origin);
-            t.set_translation_z(20.0);
-            //let rot = Rotation2::new(0.01f32);
-            updater.insert(
-                bullet,
-                Bullet {
-                    speed,
-                    direction,
-                    path_mod: None, //Some(rot),
-                                    //path_mod: Some(Isometry2::new(Vector2::new(0.0, 0.0), 0.1)),
-                },
-            );
-            updater.insert(
-                bullet,
-                SpriteRender {
-                    sprite_sheet: handle.clone(),
-                    sprite_number: 0,
-                },
-            );
-            updater.insert(bullet, t);
-            updater.insert(bullet, collider);
-            Ok(())
-        } else {
-            Err(GameError::BulletNotFound { idx: bullet_idx })
-        }
+        let collider = Collider::new_rect(
+            Vector2::new(origin.x, origin.y),
+            archetype.collider_size,
+            archetype.collider_size,
+            &mut collision.world,
+            ColliderObjectType::Bullet,
+            Some(side.collide_with()),
+            Some(bullet),
+        );
+
+        let mut t = Transform::default();
+        // don't want the bullet to fly to fast :D
+        let direction = direction.normalize();
+        t.prepend_translation(origin);
+        t.set_translation_z(20.0);
+        updater.insert(
+            bullet,
+            Bullet {
+                speed,
+                direction,
+                behavior: behavior.unwrap_or(archetype.behavior),
+                elapsed: 0.0,
+                lifetime: archetype.lifetime,
+                distance: 0.0,
+                max_range: archetype.max_range,
+                damage: archetype.damage,
+                target: None,
+                side,
+                name: name.to_string(),
+                bounces_left: archetype.bounces,
+                pierce_left: archetype.pierce,
+                knockback: archetype.knockback,
+                hit_entities: vec![],
+            },
+        );
+        updater.insert(
+            bullet,
+            SpriteRender {
+                sprite_sheet: handle.clone(),
+                sprite_number: archetype.sprite_number,
+            },
+        );
+        updater.insert(bullet, t);
+        updater.insert(bullet, collider);
+        Ok(())
     }
 }
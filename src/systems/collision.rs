@@ -1,29 +1,42 @@
 //! Manage different sort of collision
-use crate::systems::{Player, PlayerStatus};
-use crate::{event::AppEvent, util::delete_entity_with_collider};
+use crate::systems::{
+    health::Damage, melee::MeleeHitbox, visibility::PlayerVisibility, Bullet, Enemy, Player,
+    PlayerStatus,
+};
+use crate::{
+    config::{CollisionAction, CollisionConfig, PlayerConfig},
+    event::{AppEvent, EventConfig},
+};
 use amethyst::{
     core::{
-        math::{zero, Isometry2, Vector2},
+        math::{zero, Isometry2, Point2, Vector2, Vector3},
         shrev::{EventChannel, ReaderId},
+        timing::Time,
         SystemDesc, Transform,
     },
     derive::SystemDesc,
     ecs::{
-        Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadStorage, System,
-        SystemData, World, Write, WriteStorage,
+        storage::ComponentEvent, Component, DenseVecStorage, Entities, Entity, FlaggedStorage, Join,
+        NullStorage, Read, ReadStorage, ResourceId, System, SystemData, World, Write, WriteStorage,
     },
 };
 use ncollide2d::{
-    bounding_volume::{self, bounding_volume::BoundingVolume},
+    bounding_volume::{self, BoundingVolume},
     pipeline::{
-        narrow_phase::ContactEvent,
+        narrow_phase::{ContactEvent, ProximityEvent},
         object::{CollisionGroups, CollisionObjectSlabHandle, GeometricQueryType},
         world::CollisionWorld,
     },
-    shape::{Cuboid, ShapeHandle},
+    query::{self, Proximity, TOIStatus},
+    shape::{Ball, ConvexPolygon, Cuboid, Polyline, Shape, ShapeHandle},
 };
 
-use log::{debug, warn};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 
 pub struct MyCollisionWorld {
     pub world: CollisionWorld<f32, ColliderData>,
@@ -37,14 +50,239 @@ impl Default for MyCollisionWorld {
     }
 }
 
+/// How many times a movement can be deviated by the walls in one step.
+const MAX_SLIDES: usize = 3;
+
+/// Distance to keep between moving objects and walls, so that they do not start the next
+/// movement already touching the wall.
+const SKIN_WIDTH: f32 = 0.1;
+
+/// When a swept object is stopped at its time of impact, it is pushed that much further so
+/// that the contact is actually detected.
+const SWEEP_PENETRATION: f32 = 0.5;
+
+/// How long a grounded entity takes to fall when it leaves the walkable areas, in seconds.
+const FALL_DURATION: f32 = 1.0;
+
+impl MyCollisionWorld {
+    /// Move the shape of the collider from `position` by `displacement`. When the shape hits
+    /// a wall, it will slide along it instead of stopping. If the shape is already inside a wall,
+    /// it is pushed out first.
+    ///
+    /// Returns the new position. The collision object is not modified, it will be moved by the
+    /// collision system from the transform as usual.
+    pub fn move_and_slide(
+        &self,
+        collider: &Collider,
+        position: Vector2<f32>,
+        displacement: Vector2<f32>,
+    ) -> Vector2<f32> {
+        let obj = match self.world.collision_object(collider.handle) {
+            Some(obj) => obj,
+            None => {
+                warn!("Cannot find collision object to move, will ignore walls");
+                return position + displacement;
+            }
+        };
+        let shape = obj.shape().as_ref();
+        let mut groups = CollisionGroups::new();
+        groups.set_whitelist(&[ColliderObjectType::Wall.get_collider_group()]);
+
+        let mut isometry = Isometry2::new(position, obj.position().rotation.angle());
+        isometry.translation.vector += self.wall_penetration(shape, &isometry, &groups);
+
+        let mut remaining = displacement;
+        for _ in 0..MAX_SLIDES {
+            if remaining.norm_squared() <= std::f32::EPSILON {
+                break;
+            }
+
+            if let Some((toi, normal)) = self.first_wall_hit(shape, &isometry, &remaining, &groups)
+            {
+                isometry.translation.vector += remaining * toi;
+                // Remove the part of the movement that goes into the wall. What is left
+                // is parallel to the wall.
+                let rest = remaining * (1.0 - toi);
+                remaining = rest - normal * rest.dot(&normal).min(0.0);
+            } else {
+                isometry.translation.vector += remaining;
+                break;
+            }
+        }
+
+        isometry.translation.vector
+    }
+
+    /// Check whether the shape of the collider, placed at `position`, is above one of the
+    /// walkable areas.
+    pub fn is_on_walkable(&self, collider: &Collider, position: Vector2<f32>) -> bool {
+        let obj = match self.world.collision_object(collider.handle) {
+            Some(obj) => obj,
+            None => {
+                warn!("Cannot find collision object, will consider it is on the ground");
+                return true;
+            }
+        };
+        let shape = obj.shape().as_ref();
+        let mut groups = CollisionGroups::new();
+        groups.set_whitelist(&[ColliderObjectType::Walkable.get_collider_group()]);
+        let isometry = Isometry2::new(position, obj.position().rotation.angle());
+
+        let aabb = bounding_volume::aabb(shape, &isometry);
+        self.world
+            .interferences_with_aabb(&aabb, &groups)
+            .filter(|(_, area)| area.data().ty == ColliderObjectType::Walkable)
+            .any(|(_, area)| {
+                let proximity = query::proximity(
+                    &isometry,
+                    shape,
+                    area.position(),
+                    area.shape().as_ref(),
+                    0.0,
+                );
+                proximity == Proximity::Intersecting
+            })
+    }
+
+    /// Check whether the object hits something when moving from its current position in the
+    /// collision world to `target`. Only the objects that can collide with it are considered.
+    ///
+    /// Returns the position where the object should be so that the collision is detected, or
+    /// None if it can go to the target without hitting anything new.
+    pub fn swept_impact(
+        &self,
+        handle: CollisionObjectSlabHandle,
+        target: &Isometry2<f32>,
+    ) -> Option<Vector2<f32>> {
+        let obj = self.world.collision_object(handle)?;
+        let start = obj.position();
+        let displacement = target.translation.vector - start.translation.vector;
+        let distance = displacement.norm();
+        if distance <= std::f32::EPSILON {
+            return None;
+        }
+
+        let shape = obj.shape().as_ref();
+        let swept_aabb =
+            bounding_volume::aabb(shape, start).merged(&bounding_volume::aabb(shape, target));
+        let toi = self
+            .world
+            .interferences_with_aabb(&swept_aabb, obj.collision_groups())
+            .filter(|(other_handle, other)| {
+                *other_handle != handle && other.query_type().is_contacts_query()
+            })
+            .filter_map(|(_, other)| {
+                query::time_of_impact(
+                    start,
+                    &displacement,
+                    shape,
+                    other.position(),
+                    &Vector2::zeros(),
+                    other.shape().as_ref(),
+                    1.0,
+                    0.0,
+                )
+            })
+            // Objects that were already overlapping have already been detected.
+            .filter(|toi| toi.status != TOIStatus::Penetrating)
+            .map(|toi| toi.toi)
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))?;
+
+        let fraction = (toi + SWEEP_PENETRATION / distance).min(1.0);
+        Some(start.translation.vector + displacement * fraction)
+    }
+
+    /// Find the first wall that the shape will hit when moving by `displacement`. Returns the
+    /// fraction of the displacement before the impact and the normal of the wall.
+    fn first_wall_hit(
+        &self,
+        shape: &dyn Shape<f32>,
+        isometry: &Isometry2<f32>,
+        displacement: &Vector2<f32>,
+        groups: &CollisionGroups,
+    ) -> Option<(f32, Vector2<f32>)> {
+        let mut end = *isometry;
+        end.translation.vector += displacement;
+        let swept_aabb = bounding_volume::aabb(shape, isometry)
+            .merged(&bounding_volume::aabb(shape, &end))
+            .loosened(SKIN_WIDTH);
+
+        self.world
+            .interferences_with_aabb(&swept_aabb, groups)
+            .filter(|(_, wall)| wall.data().ty == ColliderObjectType::Wall)
+            .filter_map(|(_, wall)| {
+                let toi = query::time_of_impact(
+                    isometry,
+                    displacement,
+                    shape,
+                    wall.position(),
+                    &Vector2::zeros(),
+                    wall.shape().as_ref(),
+                    1.0,
+                    SKIN_WIDTH,
+                )?;
+
+                if toi.status == TOIStatus::Penetrating {
+                    // Normals are not valid in that case, so use the contact instead.
+                    let contact = query::contact(
+                        isometry,
+                        shape,
+                        wall.position(),
+                        wall.shape().as_ref(),
+                        0.0,
+                    )?;
+                    Some((0.0, -contact.normal.into_inner()))
+                } else {
+                    Some((toi.toi, wall.position().rotation * toi.normal2.into_inner()))
+                }
+            })
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+    }
+
+    /// Translation to apply to the shape so that it does not overlap with the walls anymore.
+    fn wall_penetration(
+        &self,
+        shape: &dyn Shape<f32>,
+        isometry: &Isometry2<f32>,
+        groups: &CollisionGroups,
+    ) -> Vector2<f32> {
+        let aabb = bounding_volume::aabb(shape, isometry);
+        self.world
+            .interferences_with_aabb(&aabb, groups)
+            .filter(|(_, wall)| wall.data().ty == ColliderObjectType::Wall)
+            .filter_map(|(_, wall)| {
+                query::contact(
+                    isometry,
+                    shape,
+                    wall.position(),
+                    wall.shape().as_ref(),
+                    0.0,
+                )
+            })
+            .filter(|contact| contact.depth > 0.0)
+            // Contact normal goes from the shape to the wall.
+            .fold(Vector2::zeros(), |correction, contact| {
+                correction - contact.normal.into_inner() * (contact.depth + SKIN_WIDTH)
+            })
+    }
+}
+
 /// Determine what kind of object the collider is attached to. This is useful
 /// when resolving collisions.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ColliderObjectType {
     Bullet,
     Player,
     Wall,
     Enemy,
+    /// Sensor that only detects when objects enter or leave it.
+    Trigger,
+    /// Object that can be collected by the player (weapons...)
+    Pickup,
+    /// Hitbox of a melee attack.
+    Melee,
+    /// Ground where entities can walk without falling.
+    Walkable,
     None,
 }
 
@@ -59,6 +297,19 @@ impl ColliderObjectType {
             ColliderObjectType::Wall => 3,
             ColliderObjectType::Enemy => 4,
             ColliderObjectType::None => 5,
+            ColliderObjectType::Trigger => 6,
+            ColliderObjectType::Pickup => 7,
+            ColliderObjectType::Melee => 8,
+            ColliderObjectType::Walkable => 9,
+        }
+    }
+
+    /// Triggers only need to know whether objects are inside them. Other objects
+    /// need the contacts.
+    pub fn get_query_type(&self) -> GeometricQueryType<f32> {
+        match *self {
+            ColliderObjectType::Trigger => GeometricQueryType::Proximity(0.0),
+            _ => GeometricQueryType::Contacts(0.0, 0.0),
         }
     }
 }
@@ -70,6 +321,17 @@ pub struct ColliderData {
     ty: ColliderObjectType,
 }
 
+impl ColliderData {
+    /// Entity that owns the collision object.
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    pub fn ty(&self) -> ColliderObjectType {
+        self.ty
+    }
+}
+
 impl Default for ColliderData {
     fn default() -> Self {
         Self {
@@ -79,8 +341,11 @@ impl Default for ColliderData {
     }
 }
 
+/// Handle to the collision object of the entity. The collision object is removed from the
+/// collision world by the collision system when the component is removed or the entity
+/// is deleted.
 #[derive(Debug, Component, Copy, Clone)]
-#[storage(DenseVecStorage)]
+#[storage(FlaggedStorage<Self, DenseVecStorage<Self>>)]
 pub struct Collider {
     pub handle: CollisionObjectSlabHandle,
 }
@@ -98,6 +363,88 @@ impl Collider {
         entity: Option<Entity>,
     ) -> Self {
         let rect = ShapeHandle::new(Cuboid::new(Vector2::new(w / 2.0, h / 2.0)));
+        Collider::new(
+            rect,
+            position,
+            collision_world,
+            collider_type,
+            collide_with,
+            entity,
+        )
+    }
+
+    /// Create a new collider that has a circle shape. Position is the center of the circle.
+    pub fn new_circle(
+        position: Vector2<f32>,
+        radius: f32,
+        collision_world: &mut CollisionWorld<f32, ColliderData>,
+        collider_type: ColliderObjectType,
+        collide_with: Option<&[ColliderObjectType]>,
+        entity: Option<Entity>,
+    ) -> Self {
+        let circle = ShapeHandle::new(Ball::new(radius));
+        Collider::new(
+            circle,
+            position,
+            collision_world,
+            collider_type,
+            collide_with,
+            entity,
+        )
+    }
+
+    /// Create a new collider that has a convex polygon shape. Points are relative to `position`.
+    /// If the points do not describe a convex polygon, the convex hull is used instead.
+    ///
+    /// Returns None if the points do not form a polygon (less than 3 points, all aligned...).
+    pub fn new_polygon(
+        position: Vector2<f32>,
+        points: &[Point2<f32>],
+        collision_world: &mut CollisionWorld<f32, ColliderData>,
+        collider_type: ColliderObjectType,
+        collide_with: Option<&[ColliderObjectType]>,
+        entity: Option<Entity>,
+    ) -> Option<Self> {
+        let polygon = ShapeHandle::new(ConvexPolygon::try_from_points(points)?);
+        Some(Collider::new(
+            polygon,
+            position,
+            collision_world,
+            collider_type,
+            collide_with,
+            entity,
+        ))
+    }
+
+    /// Create a new collider made of connected segments. Points are relative to `position`.
+    /// This is not a closed shape so only the segments will collide.
+    pub fn new_polyline(
+        position: Vector2<f32>,
+        points: Vec<Point2<f32>>,
+        collision_world: &mut CollisionWorld<f32, ColliderData>,
+        collider_type: ColliderObjectType,
+        collide_with: Option<&[ColliderObjectType]>,
+        entity: Option<Entity>,
+    ) -> Self {
+        let polyline = ShapeHandle::new(Polyline::new(points, None));
+        Collider::new(
+            polyline,
+            position,
+            collision_world,
+            collider_type,
+            collide_with,
+            entity,
+        )
+    }
+
+    fn new(
+        shape: ShapeHandle<f32>,
+        position: Vector2<f32>,
+        collision_world: &mut CollisionWorld<f32, ColliderData>,
+        collider_type: ColliderObjectType,
+        collide_with: Option<&[ColliderObjectType]>,
+        entity: Option<Entity>,
+    ) -> Self {
         let position = Isometry2::new(position, zero());
         let mut group = CollisionGroups::new();
         group.set_membership(&[collider_type.get_collider_group()]);
@@ -112,13 +459,11 @@ impl Collider {
             );
         }
 
-        let contacts_query = GeometricQueryType::Contacts(0.0, 0.0);
-
         let (handle, _) = collision_world.add(
             position,
-            rect,
+            shape,
             group,
-            contacts_query,
+            collider_type.get_query_type(),
             ColliderData {
                 entity,
                 ty: collider_type,
@@ -144,8 +489,38 @@ impl Collider {
 #[storage(NullStorage)]
 pub struct Walkable;
 
+/// Entities that can only stay on the walkable areas. They fall and die when they are pushed
+/// out of them. The player has its own falling logic.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(DenseVecStorage)]
+pub struct Grounded {
+    /// Time since the entity started falling. None while it is on the ground.
+    pub falling: Option<f32>,
+}
+
+impl Grounded {
+    pub fn is_falling(&self) -> bool {
+        self.falling.is_some()
+    }
+}
+
+/// Send events when an object enters or leaves the collider of the entity. The collider
+/// should have the `Trigger` type.
+#[derive(Debug, Clone, Default, Component)]
+#[storage(DenseVecStorage)]
+pub struct Trigger {
+    pub on_enter: Option<EventConfig>,
+    pub on_exit: Option<EventConfig>,
+    /// Remove the trigger after it has been entered once.
+    pub once: bool,
+}
+
 /// Will detect whether the player can still walk. This is
-/// determine by checking if the player collider is still in the
+/// determine by checking if the player collider still intersects one of the
+/// walkable areas. The player can stay out of the walkable areas for a short time
+/// before falling.
+///
+/// Other entities with the `Grounded` component fall and die as soon as they leave the
 /// walkable areas.
 #[derive(Default, Debug, SystemDesc)]
 pub struct WalkableSystem;
@@ -153,54 +528,88 @@ pub struct WalkableSystem;
 impl<'s> System<'s> for WalkableSystem {
     type SystemData = (
         WriteStorage<'s, Player>,
+        WriteStorage<'s, Grounded>,
+        WriteStorage<'s, Transform>,
         ReadStorage<'s, Collider>,
-        ReadStorage<'s, Walkable>,
+        Entities<'s>,
         Read<'s, MyCollisionWorld>,
+        Read<'s, PlayerConfig>,
+        Read<'s, Time>,
+        Write<'s, EventChannel<AppEvent>>,
     );
 
     fn run(
         &mut self,
-        (mut players, colliders2, walkable_areas, collision_world): Self::SystemData,
+        (
+            mut players,
+            mut grounded,
+            mut transforms,
+            colliders,
+            entities,
+            collision_world,
+            player_config,
+            time,
+            mut events,
+        ): Self::SystemData,
     ) {
-        for (player, collider) in (&mut players, &colliders2).join() {
-            // If player is not walking, ignore the rest
+        for (player, collider) in (&mut players, &colliders).join() {
+            // If player is not walking, ignore the rest. A dashing player can cross the gaps.
             if let PlayerStatus::Walking = player.state {
-                let mut player_in_area = false;
-
                 let cob = collision_world.world.collision_object(collider.handle);
                 if let None = cob {
                     warn!("No collider for the handle in player");
                     continue;
                 }
-                let cob = cob.unwrap();
-                let aabb = bounding_volume::aabb(cob.shape().as_ref(), cob.position());
-                for (_area, area_collider) in (&walkable_areas, &colliders2).join() {
-                    let aob = collision_world.world.collision_object(area_collider.handle);
-
-                    if let None = aob {
-                        warn!("No collider for the handle in walkable area");
-                        continue;
-                    }
-                    let aob = aob.unwrap();
-                    let area_aabb = bounding_volume::aabb(aob.shape().as_ref(), aob.position());
+                let position = cob.unwrap().position().translation.vector;
+                let player_in_area = collision_world.is_on_walkable(collider, position);
 
-                    if area_aabb.intersects(&aabb) {
-                        player_in_area = true;
-                        break;
+                // Now we know whether the player is above ground. If it's not, then he should
+                // fall. I don't send game over event right now. Rather, the player fall animation
+                // needs to be played first.
+                if player_in_area {
+                    player.off_ground_time = 0.0;
+                    player.last_safe_position = Some(position);
+                } else {
+                    player.off_ground_time += time.delta_seconds();
+                    if player.off_ground_time > player_config.fall.grace_time {
+                        player.state = PlayerStatus::Falling {
+                            falling_duration: 1.0,
+                            elapsed_time: 0.0,
+                        };
                     }
                 }
+            }
+        }
 
-                // Now we know whether the player is above ground. If it's not, then he should
-                // fall and the game is over. I don't send game over event right now. Rather, the
-                // player fall animation needs to be played first.
-                if !player_in_area {
-                    player.state = PlayerStatus::Falling {
-                        falling_duration: 1.0,
-                        elapsed_time: 0.0,
-                    };
+        let mut events_to_send = vec![];
+        for (grounded, collider, transform, entity) in
+            (&mut grounded, &colliders, &mut transforms, &entities).join()
+        {
+            match grounded.falling {
+                None => {
+                    let position = transform.translation().xy();
+                    if !collision_world.is_on_walkable(collider, position) {
+                        debug!("Entity {:?} is falling", entity);
+                        grounded.falling = Some(0.0);
+                    }
+                }
+                Some(previous_time) => {
+                    // Shrink until it disappears, then it is dead.
+                    let elapsed_time = previous_time + time.delta_seconds();
+                    let scale = ((FALL_DURATION - elapsed_time) / FALL_DURATION).max(0.0);
+                    transform.set_scale(Vector3::new(scale, scale, scale));
+                    if previous_time <= FALL_DURATION && elapsed_time > FALL_DURATION {
+                        events_to_send.push(AppEvent::EntityHit {
+                            target: entity,
+                            attacker: None,
+                            damage: Damage::lethal(),
+                        });
+                    }
+                    grounded.falling = Some(elapsed_time);
                 }
             }
         }
+        events.drain_vec_write(&mut events_to_send);
     }
 }
 
@@ -209,27 +618,64 @@ impl<'s> System<'s> for WalkableSystem {
 pub struct CollisionSystem {
     #[system_desc(event_channel_reader)]
     reader_id: ReaderId<AppEvent>,
+
+    #[system_desc(flagged_storage_reader(Collider))]
+    collider_reader_id: ReaderId<ComponentEvent>,
+
+    /// Collision object handles by entity index. When a collider is removed, its data
+    /// is not available anymore so the handle has to be kept here.
+    #[system_desc(skip)]
+    handles: HashMap<u32, CollisionObjectSlabHandle>,
+
+    /// Indexes of the entities deleted by this system. Their collision object is already
+    /// removed so there is nothing to do when their collider removal is seen.
+    #[system_desc(skip)]
+    deleted: HashSet<u32>,
+}
+
+/// Storages used by the actions of the collision response table.
+#[derive(SystemData)]
+pub struct ResponseData<'s> {
+    bullets: WriteStorage<'s, Bullet>,
+    hitboxes: WriteStorage<'s, MeleeHitbox>,
+    transforms: WriteStorage<'s, Transform>,
+    players: ReadStorage<'s, Player>,
+    enemies: ReadStorage<'s, Enemy>,
+    visibility: Write<'s, PlayerVisibility>,
+    channel: Write<'s, EventChannel<AppEvent>>,
 }
 
 impl<'s> System<'s> for CollisionSystem {
     type SystemData = (
         ReadStorage<'s, Collider>,
-        ReadStorage<'s, Transform>,
         Write<'s, MyCollisionWorld>,
         Entities<'s>,
-        Write<'s, EventChannel<AppEvent>>,
+        Read<'s, CollisionConfig>,
+        ReadStorage<'s, Trigger>,
+        ResponseData<'s>,
     );
 
     fn run(
         &mut self,
-        (colliders, transforms, mut collision_world, entities, mut channel): Self::SystemData,
+        (
+            colliders,
+            mut collision_world,
+            entities,
+            collision_config,
+            triggers,
+            mut data,
+        ): Self::SystemData,
     ) {
+        // First remove the collision objects of the entities that have been deleted so that
+        // the contact events do not refer to them anymore.
+        self.sync_colliders(&colliders, &entities, &mut collision_world);
+
         // handle here all the collision events.
         let mut to_remove = vec![];
 
         // delete all the entities that should be deleted. This is done
         // here because convenient access to all relevant systems :)
-        for ev in channel.read(&mut self.reader_id) {
+        for ev in data.channel.read(&mut self.reader_id) {
             match ev {
                 AppEvent::EnemyDied(e) => {
                     to_remove.push(*e);
@@ -240,15 +686,36 @@ impl<'s> System<'s> for CollisionSystem {
 
         for event in collision_world.world.contact_events() {
             let mut to_remove_from_ev =
-                self.handle_contact_event(&collision_world.world, event, &mut channel);
+                handle_contact_event(&collision_world.world, event, &collision_config, &mut data);
+            to_remove.append(&mut to_remove_from_ev);
+        }
+
+        for event in collision_world.world.proximity_events() {
+            let mut to_remove_from_ev = self.handle_proximity_event(
+                &collision_world.world,
+                event,
+                &triggers,
+                &mut data.channel,
+            );
             to_remove.append(&mut to_remove_from_ev);
         }
         // now update all the positions and update the world.
-        self.update_obj_positions(&colliders, &transforms, &mut collision_world);
+        self.update_obj_positions(&colliders, &mut data.transforms, &mut collision_world);
 
-        // Remove stuff to be destructed...
+        // Remove stuff to be destructed... Their collision objects are removed now so that
+        // they are not part of the update.
         for entity in to_remove {
-            delete_entity_with_collider(entity, &colliders, &entities, &mut collision_world.world);
+            if entities.is_alive(entity) {
+                if let Some(handle) = self.handles.remove(&entity.id()) {
+                    remove_collision_object(&mut collision_world, handle);
+                    self.deleted.insert(entity.id());
+                }
+            }
+            // what can happen is a wrong generation error if the entity was removed
+            // twice.
+            if let Err(e) = entities.delete(entity) {
+                error!("{}", e);
+            }
         }
 
         collision_world.world.update();
@@ -256,23 +723,92 @@ impl<'s> System<'s> for CollisionSystem {
 }
 
 impl CollisionSystem {
-    pub fn new(reader_id: ReaderId<AppEvent>) -> Self {
-        Self { reader_id }
+    pub fn new(
+        reader_id: ReaderId<AppEvent>,
+        collider_reader_id: ReaderId<ComponentEvent>,
+    ) -> Self {
+        Self {
+            reader_id,
+            collider_reader_id,
+            handles: HashMap::new(),
+            deleted: HashSet::new(),
+        }
+    }
+
+    /// Keep the collision world in sync with the `Collider` components. Removing the component
+    /// or deleting the entity will remove the collision object.
+    ///
+    /// The events are processed in order, so a collider that was inserted and removed since the
+    /// last frame is removed as well.
+    fn sync_colliders(
+        &mut self,
+        colliders: &ReadStorage<Collider>,
+        entities: &Entities,
+        collision_world: &mut MyCollisionWorld,
+    ) {
+        // The entities deleted on the last frame have been removed from the storages since.
+        let deleted = std::mem::replace(&mut self.deleted, HashSet::new());
+        let mut orphans = HashSet::new();
+        for event in colliders.channel().read(&mut self.collider_reader_id) {
+            match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    // Not there anymore if it was removed later in the same batch.
+                    if let Some(collider) = colliders.get(entities.entity(*id)) {
+                        if let Some(previous) = self.handles.insert(*id, collider.handle) {
+                            // The component has been replaced, so the old object is not used
+                            // anymore.
+                            if previous != collider.handle {
+                                remove_collision_object(collision_world, previous);
+                            }
+                        }
+                    }
+                }
+                ComponentEvent::Removed(id) => {
+                    if let Some(handle) = self.handles.remove(id) {
+                        remove_collision_object(collision_world, handle);
+                    } else if !deleted.contains(id) {
+                        orphans.insert(*id);
+                    }
+                }
+            }
+        }
+
+        if !orphans.is_empty() {
+            remove_orphan_objects(collision_world, entities, &orphans);
+        }
     }
 
     /// Will update the collision object positiosn from the transform attached to the
     /// same entity.
+    ///
+    /// Bullets can move fast enough to go through a wall between two frames, so they are
+    /// swept from their previous position and stopped at the first impact.
     fn update_obj_positions(
         &self,
         colliders: &ReadStorage<Collider>,
-        transforms: &ReadStorage<Transform>,
+        transforms: &mut WriteStorage<Transform>,
         collision_world: &mut Write<MyCollisionWorld>,
     ) {
         for (collider, transform) in (colliders, transforms).join() {
+            let translation_xy = transform.translation().xy();
+            let angle = transform.rotation().angle();
+            let mut isometry = Isometry2::new(translation_xy, angle);
+
+            let is_bullet = collision_world
+                .world
+                .collision_object(collider.handle)
+                .map(|obj| obj.data().ty == ColliderObjectType::Bullet)
+                .unwrap_or(false);
+            if is_bullet {
+                if let Some(impact) = collision_world.swept_impact(collider.handle, &isometry) {
+                    debug!("Bullet stopped at {:?} instead of {:?}", impact, translation_xy);
+                    isometry.translation.vector = impact;
+                    transform.set_translation_x(impact.x);
+                    transform.set_translation_y(impact.y);
+                }
+            }
+
             if let Some(obj) = collision_world.world.get_mut(collider.handle) {
-                let translation_xy = transform.translation().xy();
-                let angle = transform.rotation().angle();
-                let isometry = Isometry2::new(translation_xy, angle);
                 obj.set_position(isometry);
             } else {
                 warn!("Cannot find collision object for collider");
@@ -280,112 +816,655 @@ impl CollisionSystem {
         }
     }
 
-    fn handle_contact_event(
+    /// Send the events of the trigger when an object enters or leaves it. Returns the triggers
+    /// that need to be removed.
+    fn handle_proximity_event(
         &self,
         world: &CollisionWorld<f32, ColliderData>,
-        event: &ContactEvent<CollisionObjectSlabHandle>,
+        event: &ProximityEvent<CollisionObjectSlabHandle>,
+        triggers: &ReadStorage<Trigger>,
         channel: &mut Write<EventChannel<AppEvent>>,
     ) -> Vec<Entity> {
         let mut to_remove = vec![];
-        if let &ContactEvent::Started(collider1, collider2) = event {
-            // maybe remaining event from last frame before we deleted the colliders...
-            match (
-                world.collision_object(collider1),
-                world.collision_object(collider2),
-            ) {
-                (Some(obj1), Some(obj2)) => {
-                    debug!(
-                        "OBJ1 {:?} collided with OBJ2 {:?}",
-                        obj1.data(),
-                        obj2.data()
-                    );
-                    match (obj1.data().ty, obj2.data().ty) {
-                        (ColliderObjectType::Player, ColliderObjectType::Bullet) => {
-                            debug!("Object 1 (Player) collided with object 2 (Bullet)");
-                            to_remove.push(
-                                obj2.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-                            //entities.delete(obj2.data().ty);
-                            self.send_hit_event(
-                                channel,
-                                obj1.data()
-                                    .entity
-                                    .expect("player collider should have entity"),
-                            );
-                        }
-                        (ColliderObjectType::Bullet, ColliderObjectType::Player) => {
-                            debug!("Object 1 (Bullet) collided with object 2 (Player)");
-                            to_remove.push(
-                                obj1.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-
-                            self.send_hit_event(
-                                channel,
-                                obj2.data()
-                                    .entity
-                                    .expect("player collider should have entity"),
-                            );
-                        }
-                        (ColliderObjectType::Bullet, ColliderObjectType::Wall) => {
-                            debug!("Bullet (1) collided with wall (2)");
-                            to_remove.push(
-                                obj1.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-                        }
-                        (ColliderObjectType::Wall, ColliderObjectType::Bullet) => {
-                            debug!("Bullet (1) collided with wall (1)");
-                            to_remove.push(
-                                obj2.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-                        }
-                        (ColliderObjectType::Bullet, ColliderObjectType::Enemy) => {
-                            debug!("Bullet (1) collided with enemy (2)");
-                            to_remove.push(
-                                obj1.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-                            self.send_hit_event(
-                                channel,
-                                obj2.data()
-                                    .entity
-                                    .expect("enemy collider should have entity"),
-                            );
-                        }
-                        (ColliderObjectType::Enemy, ColliderObjectType::Bullet) => {
-                            debug!("Bullet (2) collided with enemy (1)");
-                            to_remove.push(
-                                obj2.data()
-                                    .entity
-                                    .expect("Bullet should have an entity in its data"),
-                            );
-                            self.send_hit_event(
-                                channel,
-                                obj1.data()
-                                    .entity
-                                    .expect("enemy collider should have entity"),
-                            );
-                        }
+        let entered = event.new_status == Proximity::Intersecting;
+        let exited = event.prev_status == Proximity::Intersecting;
+        if !entered && !exited {
+            return to_remove;
+        }
+
+        // Do not know which one of the two is the trigger.
+        for handle in &[event.collider1, event.collider2] {
+            let trigger_entity = world
+                .collision_object(*handle)
+                .filter(|obj| obj.data().ty == ColliderObjectType::Trigger)
+                .and_then(|obj| obj.data().entity);
+
+            if let Some(entity) = trigger_entity {
+                if let Some(trigger) = triggers.get(entity) {
+                    debug!("Trigger {:?} (entered = {})", trigger, entered);
+                    let ev = if entered {
+                        &trigger.on_enter
+                    } else {
+                        &trigger.on_exit
+                    };
+                    if let Some(ev) = ev {
+                        channel.single_write(ev.to_event());
+                    }
 
-                        _ => (),
+                    if entered && trigger.once {
+                        to_remove.push(entity);
                     }
                 }
-                _ => (),
             }
         }
 
         to_remove
     }
 
-    fn send_hit_event(&self, channel: &mut Write<EventChannel<AppEvent>>, entity: Entity) {
-        channel.single_write(AppEvent::EntityHit(entity));
+}
+
+/// Look up the collision response table for the pair of objects that started colliding
+/// and apply its actions. Returns the entities that need to be removed.
+fn handle_contact_event(
+    world: &CollisionWorld<f32, ColliderData>,
+    event: &ContactEvent<CollisionObjectSlabHandle>,
+    collision_config: &CollisionConfig,
+    data: &mut ResponseData,
+) -> Vec<Entity> {
+    let mut to_remove = vec![];
+    let (collider1, collider2) = match *event {
+        ContactEvent::Started(collider1, collider2) => (collider1, collider2),
+        ContactEvent::Stopped(..) => return to_remove,
+    };
+    // maybe remaining event from last frame before we deleted the colliders...
+    let (obj1, obj2) = match (
+        world.collision_object(collider1),
+        world.collision_object(collider2),
+    ) {
+        (Some(obj1), Some(obj2)) => (obj1, obj2),
+        _ => return to_remove,
+    };
+    debug!("OBJ1 {:?} collided with OBJ2 {:?}", obj1.data(), obj2.data());
+
+    // A piercing bullet can touch the same entity several times while going through it.
+    if already_hit(&data.bullets, obj1.data(), obj2.data())
+        || already_hit(&data.bullets, obj2.data(), obj1.data())
+    {
+        return to_remove;
+    }
+
+    // Bullets go through the player while it dashes.
+    if dodges(&data.players, obj1.data(), obj2.data())
+        || dodges(&data.players, obj2.data(), obj1.data())
+    {
+        return to_remove;
+    }
+
+    let (response, swapped) = match collision_config.find(obj1.data().ty, obj2.data().ty) {
+        Some(found) => found,
+        None => {
+            debug!(
+                "No collision response for {:?} and {:?}",
+                obj1.data().ty,
+                obj2.data().ty
+            );
+            return to_remove;
+        }
+    };
+
+    // First should be the object that has the type `response.first`
+    let (first, second) = if swapped {
+        ((collider2, obj2.data()), (collider1, obj1.data()))
+    } else {
+        ((collider1, obj1.data()), (collider2, obj2.data()))
+    };
+
+    for action in &response.actions {
+        match action {
+            CollisionAction::Despawn(side) => {
+                if let Some(e) = side.pick(&first, &second).1.entity {
+                    to_remove.push(e);
+                } else {
+                    warn!("Cannot despawn {:?}, no entity", side);
+                }
+            }
+            CollisionAction::Hit(side) => {
+                if let Some(target) = side.pick(&first, &second).1.entity {
+                    let attacker = side.other().pick(&first, &second).1.entity;
+                    if let Some((attacker, damage)) = hit_damage(data, target, attacker) {
+                        data.channel.single_write(AppEvent::EntityHit {
+                            target,
+                            attacker,
+                            damage,
+                        });
+                    }
+                } else {
+                    warn!("Cannot hit {:?}, no entity", side);
+                }
+            }
+            CollisionAction::Emit(ev) => data.channel.single_write(ev.to_event()),
+            CollisionAction::Bounce(side) => {
+                let (handle, bullet_data) = side.pick(&first, &second);
+                let (other_handle, _) = side.other().pick(&first, &second);
+                if let Some(e) = bullet_data.entity {
+                    let transforms = &mut data.transforms;
+                    let bounced = data.bullets.get_mut(e).map_or(false, |bullet| {
+                        bounce(world, *handle, *other_handle, bullet, transforms.get_mut(e))
+                    });
+                    if !bounced {
+                        to_remove.push(e);
+                    }
+                } else {
+                    warn!("Cannot bounce {:?}, no entity", side);
+                }
+            }
+            CollisionAction::Pierce(side) => {
+                let (_, bullet_data) = side.pick(&first, &second);
+                let (_, other) = side.other().pick(&first, &second);
+                if let Some(e) = bullet_data.entity {
+                    let pierced = data.bullets.get_mut(e).map_or(false, |bullet| {
+                        if let Some(other) = other.entity {
+                            bullet.hit_entities.push(other);
+                        }
+                        if bullet.pierce_left > 0 {
+                            bullet.pierce_left -= 1;
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                    if !pierced {
+                        to_remove.push(e);
+                    }
+                } else {
+                    warn!("Cannot pierce {:?}, no entity", side);
+                }
+            }
+            CollisionAction::Collect(side) => {
+                let pickup = side.pick(&first, &second).1.entity;
+                let collector = side.other().pick(&first, &second).1.entity;
+                if let (Some(pickup), Some(collector)) = (pickup, collector) {
+                    data.channel.single_write(AppEvent::Collect { pickup, collector });
+                } else {
+                    warn!("Cannot collect {:?}, no entity", side);
+                }
+            }
+        }
+    }
+
+    to_remove
+}
+
+/// Damage dealt to `target` and the attacker to report in the hit event. Melee hitboxes
+/// report the entity that attacks and hit each target only once (None after the first hit).
+fn hit_damage(
+    data: &mut ResponseData,
+    target: Entity,
+    attacker: Option<Entity>,
+) -> Option<(Option<Entity>, Damage)> {
+    let attacker = match attacker {
+        Some(attacker) => attacker,
+        None => return Some((None, Damage::default())),
+    };
+    if let Some(bullet) = data.bullets.get(attacker) {
+        return Some((Some(attacker), bullet.damage));
+    }
+    if let Some(hitbox) = data.hitboxes.get_mut(attacker) {
+        let damage = hitbox.strike(
+            target,
+            data.enemies.get(target),
+            data.transforms.get(target),
+            &mut data.visibility,
+        )?;
+        return Some((Some(hitbox.attacker), damage));
+    }
+    Some((Some(attacker), Damage::default()))
+}
+
+/// True if the object `bullet` is a bullet that has already hit the entity of `other`.
+fn already_hit(
+    bullets: &WriteStorage<Bullet>,
+    bullet: &ColliderData,
+    other: &ColliderData,
+) -> bool {
+    match (bullet.entity, other.entity) {
+        (Some(bullet), Some(other)) => bullets
+            .get(bullet)
+            .map_or(false, |bullet| bullet.hit_entities.contains(&other)),
+        _ => false,
+    }
+}
+
+/// True if the object `player` is an invincible player and `other` is a bullet.
+fn dodges(players: &ReadStorage<Player>, player: &ColliderData, other: &ColliderData) -> bool {
+    other.ty == ColliderObjectType::Bullet
+        && player
+            .entity
+            .and_then(|e| players.get(e))
+            .map_or(false, Player::is_invincible)
+}
+
+/// Reflect the direction of the bullet off the contact normal and push it out of the
+/// other object. Returns false when the bullet cannot bounce anymore.
+fn bounce(
+    world: &CollisionWorld<f32, ColliderData>,
+    bullet_handle: CollisionObjectSlabHandle,
+    other_handle: CollisionObjectSlabHandle,
+    bullet: &mut Bullet,
+    transform: Option<&mut Transform>,
+) -> bool {
+    if bullet.bounces_left == 0 {
+        return false;
+    }
+
+    let contact = world
+        .contact_pair(bullet_handle, other_handle, true)
+        .and_then(|(handle1, _, _, manifold)| {
+            manifold
+                .deepest_contact()
+                .map(|tracked| (handle1, tracked.contact.clone()))
+        });
+
+    if let Some((handle1, contact)) = contact {
+        // The normal goes from the first object of the pair to the second one.
+        let normal = if handle1 == bullet_handle {
+            -contact.normal.into_inner()
+        } else {
+            contact.normal.into_inner()
+        };
+
+        bullet.bounces_left -= 1;
+        bullet.direction -= 2.0 * bullet.direction.dot(&normal) * normal;
+        if let Some(transform) = transform {
+            let push = normal * contact.depth;
+            transform.prepend_translation_x(push.x);
+            transform.prepend_translation_y(push.y);
+        }
+        true
+    } else {
+        warn!("Cannot find the contact to bounce off");
+        false
+    }
+}
+
+/// Remove the object from the world. Removing a handle that is not in the world anymore
+/// would panic.
+fn remove_collision_object(
+    collision_world: &mut MyCollisionWorld,
+    handle: CollisionObjectSlabHandle,
+) {
+    if collision_world.world.collision_object(handle).is_some() {
+        collision_world.world.remove(&[handle]);
+    } else {
+        warn!("Collision world could not find the handle of the removed collider");
+    }
+}
+
+/// Remove the collision objects of the deleted entities whose collider was removed before its
+/// insertion could be seen. This is rare so it is fine to go through all the objects.
+///
+/// Only dead owners are matched. The ids are reused right away, and a new entity with the same
+/// id can already have its collision object while its collider is still being inserted.
+fn remove_orphan_objects(
+    collision_world: &mut MyCollisionWorld,
+    entities: &Entities,
+    ids: &HashSet<u32>,
+) {
+    let orphans = collision_world
+        .world
+        .collision_objects()
+        .filter(|(_, obj)| {
+            obj.data()
+                .entity
+                .map_or(false, |e| ids.contains(&e.id()) && !entities.is_alive(e))
+        })
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    if !orphans.is_empty() {
+        collision_world.world.remove(&orphans);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{CollisionResponse, CollisionSide},
+        systems::{health::DamageType, BulletBehavior, BulletSide},
+    };
+    use amethyst::ecs::{Builder, WorldExt};
+
+    struct Setup {
+        world: World,
+        collision: MyCollisionWorld,
+        reader: ReaderId<AppEvent>,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let mut world = World::new();
+            world.register::<Bullet>();
+            world.register::<MeleeHitbox>();
+            world.register::<Transform>();
+            world.register::<Player>();
+            world.register::<Enemy>();
+            world.insert(PlayerVisibility::default());
+            let mut channel = EventChannel::<AppEvent>::new();
+            let reader = channel.register_reader();
+            world.insert(channel);
+            Self {
+                world,
+                collision: MyCollisionWorld::default(),
+                reader,
+            }
+        }
+
+        /// Add an entity with a 10x10 square collider centered on (x, 0).
+        fn add(
+            &mut self,
+            ty: ColliderObjectType,
+            x: f32,
+            bullet: Option<Bullet>,
+        ) -> (Entity, CollisionObjectSlabHandle) {
+            let mut transform = Transform::default();
+            transform.set_translation_x(x);
+            let mut builder = self.world.create_entity().with(transform);
+            if let Some(bullet) = bullet {
+                builder = builder.with(bullet);
+            }
+            let entity = builder.build();
+            let collider = Collider::new_rect(
+                Vector2::new(x, 0.0),
+                10.0,
+                10.0,
+                &mut self.collision.world,
+                ty,
+                None,
+                Some(entity),
+            );
+            (entity, collider.handle)
+        }
+
+        /// Run the responses of `config` for a contact that starts between the two objects.
+        fn contact(
+            &self,
+            config: &CollisionConfig,
+            collider1: CollisionObjectSlabHandle,
+            collider2: CollisionObjectSlabHandle,
+        ) -> Vec<Entity> {
+            let mut data = self.world.system_data::<ResponseData>();
+            let event = ContactEvent::Started(collider1, collider2);
+            handle_contact_event(&self.collision.world, &event, config, &mut data)
+        }
+
+        fn events(&mut self) -> Vec<AppEvent> {
+            self.world
+                .read_resource::<EventChannel<AppEvent>>()
+                .read(&mut self.reader)
+                .cloned()
+                .collect()
+        }
+
+        fn bullet(&self, entity: Entity) -> Bullet {
+            self.world.read_storage::<Bullet>().get(entity).unwrap().clone()
+        }
+    }
+
+    fn new_bullet(bounces_left: u32, pierce_left: u32) -> Bullet {
+        Bullet {
+            speed: 10.0,
+            direction: Vector2::new(1.0, 0.0),
+            behavior: BulletBehavior::Straight,
+            elapsed: 0.0,
+            lifetime: None,
+            distance: 0.0,
+            max_range: None,
+            damage: Damage::new(3, DamageType::Fire),
+            target: None,
+            side: BulletSide::Player,
+            name: String::from("test"),
+            bounces_left,
+            pierce_left,
+            knockback: 0.0,
+            hit_entities: vec![],
+        }
+    }
+
+    fn table(
+        first: ColliderObjectType,
+        second: ColliderObjectType,
+        actions: Vec<CollisionAction>,
+    ) -> CollisionConfig {
+        CollisionConfig {
+            responses: vec![CollisionResponse {
+                first,
+                second,
+                actions,
+            }],
+        }
+    }
+
+    #[test]
+    fn swapped_pair_uses_the_same_response() {
+        let mut setup = Setup::new();
+        let (bullet_entity, bullet) = setup.add(ColliderObjectType::Bullet, 0.0, None);
+        let (_, enemy) = setup.add(ColliderObjectType::Enemy, 5.0, None);
+        let config = table(
+            ColliderObjectType::Bullet,
+            ColliderObjectType::Enemy,
+            vec![CollisionAction::Despawn(CollisionSide::First)],
+        );
+
+        assert_eq!(setup.contact(&config, bullet, enemy), vec![bullet_entity]);
+        assert_eq!(setup.contact(&config, enemy, bullet), vec![bullet_entity]);
+    }
+
+    #[test]
+    fn missing_pair_does_nothing() {
+        let mut setup = Setup::new();
+        let (_, enemy) = setup.add(ColliderObjectType::Enemy, 0.0, None);
+        let (_, wall) = setup.add(ColliderObjectType::Wall, 5.0, None);
+        let config = CollisionConfig::default();
+
+        assert!(config
+            .find(ColliderObjectType::Enemy, ColliderObjectType::Wall)
+            .is_none());
+        assert!(setup.contact(&config, enemy, wall).is_empty());
+        assert!(setup.events().is_empty());
+    }
+
+    #[test]
+    fn default_table_hits_the_enemies_with_the_bullets() {
+        let mut setup = Setup::new();
+        let (bullet_entity, bullet) =
+            setup.add(ColliderObjectType::Bullet, 0.0, Some(new_bullet(0, 0)));
+        let (enemy_entity, enemy) = setup.add(ColliderObjectType::Enemy, 5.0, None);
+
+        // The bullet cannot pierce so it is removed.
+        let removed = setup.contact(&CollisionConfig::default(), enemy, bullet);
+        assert_eq!(removed, vec![bullet_entity]);
+        match &setup.events()[..] {
+            [AppEvent::EntityHit {
+                target,
+                attacker,
+                damage,
+            }] => {
+                assert_eq!(*target, enemy_entity);
+                assert_eq!(*attacker, Some(bullet_entity));
+                assert_eq!(*damage, Damage::new(3, DamageType::Fire));
+            }
+            events => panic!("Unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn despawn_removes_the_given_side() {
+        let mut setup = Setup::new();
+        let (_, player) = setup.add(ColliderObjectType::Player, 0.0, None);
+        let (pickup_entity, pickup) = setup.add(ColliderObjectType::Pickup, 5.0, None);
+        let config = table(
+            ColliderObjectType::Player,
+            ColliderObjectType::Pickup,
+            vec![CollisionAction::Despawn(CollisionSide::Second)],
+        );
+
+        assert_eq!(setup.contact(&config, player, pickup), vec![pickup_entity]);
+    }
+
+    #[test]
+    fn hit_without_bullet_deals_the_default_damage() {
+        let mut setup = Setup::new();
+        let (player_entity, player) = setup.add(ColliderObjectType::Player, 0.0, None);
+        let (enemy_entity, enemy) = setup.add(ColliderObjectType::Enemy, 5.0, None);
+        let config = table(
+            ColliderObjectType::Enemy,
+            ColliderObjectType::Player,
+            vec![CollisionAction::Hit(CollisionSide::Second)],
+        );
+
+        assert!(setup.contact(&config, player, enemy).is_empty());
+        match &setup.events()[..] {
+            [AppEvent::EntityHit {
+                target,
+                attacker,
+                damage,
+            }] => {
+                assert_eq!(*target, player_entity);
+                assert_eq!(*attacker, Some(enemy_entity));
+                assert_eq!(*damage, Damage::default());
+            }
+            events => panic!("Unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn emit_sends_the_event() {
+        let mut setup = Setup::new();
+        let (_, player) = setup.add(ColliderObjectType::Player, 0.0, None);
+        let (_, wall) = setup.add(ColliderObjectType::Wall, 5.0, None);
+        let config = table(
+            ColliderObjectType::Player,
+            ColliderObjectType::Wall,
+            vec![CollisionAction::Emit(EventConfig::OpenDoor(String::from("gate")))],
+        );
+
+        assert!(setup.contact(&config, wall, player).is_empty());
+        match &setup.events()[..] {
+            [AppEvent::OpenDoor(name)] => assert_eq!(name, "gate"),
+            events => panic!("Unexpected events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn bounce_reflects_the_bullet_until_it_cannot_bounce() {
+        let mut setup = Setup::new();
+        let (bullet_entity, bullet) =
+            setup.add(ColliderObjectType::Bullet, 0.0, Some(new_bullet(1, 0)));
+        let (_, wall) = setup.add(ColliderObjectType::Wall, 8.0, None);
+        // The contacts are needed to find the normal.
+        setup.collision.world.update();
+        let config = table(
+            ColliderObjectType::Bullet,
+            ColliderObjectType::Wall,
+            vec![CollisionAction::Bounce(CollisionSide::First)],
+        );
+
+        assert!(setup.contact(&config, wall, bullet).is_empty());
+        let bounced = setup.bullet(bullet_entity);
+        assert_eq!(bounced.bounces_left, 0);
+        assert!(bounced.direction.x < 0.0);
+        {
+            let transforms = setup.world.read_storage::<Transform>();
+            let x = transforms.get(bullet_entity).unwrap().translation().x;
+            assert!(x < 0.0, "The bullet should be pushed out of the wall");
+        }
+
+        assert_eq!(setup.contact(&config, bullet, wall), vec![bullet_entity]);
+    }
+
+    #[test]
+    fn pierce_goes_through_each_entity_once() {
+        let mut setup = Setup::new();
+        let (bullet_entity, bullet) =
+            setup.add(ColliderObjectType::Bullet, 0.0, Some(new_bullet(0, 1)));
+        let (enemy_entity, enemy) = setup.add(ColliderObjectType::Enemy, 5.0, None);
+        let (_, other_enemy) = setup.add(ColliderObjectType::Enemy, -5.0, None);
+        let config = table(
+            ColliderObjectType::Bullet,
+            ColliderObjectType::Enemy,
+            vec![CollisionAction::Pierce(CollisionSide::First)],
+        );
+
+        assert!(setup.contact(&config, bullet, enemy).is_empty());
+        let pierced = setup.bullet(bullet_entity);
+        assert_eq!(pierced.pierce_left, 0);
+        assert_eq!(pierced.hit_entities, vec![enemy_entity]);
+
+        // Touching the same enemy again does nothing.
+        assert!(setup.contact(&config, enemy, bullet).is_empty());
+        // It cannot go through another one.
+        assert_eq!(setup.contact(&config, bullet, other_enemy), vec![bullet_entity]);
+    }
+
+    #[test]
+    fn melee_hitbox_hits_each_enemy_once() {
+        let mut setup = Setup::new();
+        let (player_entity, _) = setup.add(ColliderObjectType::Player, -10.0, None);
+        let (hitbox_entity, hitbox) = setup.add(ColliderObjectType::Melee, 0.0, None);
+        let (enemy_entity, enemy) = setup.add(ColliderObjectType::Enemy, 5.0, None);
+        let damage = Damage::new(2, DamageType::Physical);
+        setup
+            .world
+            .write_storage::<MeleeHitbox>()
+            .insert(
+                hitbox_entity,
+                MeleeHitbox {
+                    attacker: player_entity,
+                    origin: Vector2::new(-10.0, 0.0),
+                    damage,
+                    noise: 0.3,
+                    takedown_angle: 0.0,
+                    lifetime: 1.0,
+                    hit_entities: vec![],
+                },
+            )
+            .unwrap();
+        let config = CollisionConfig::default();
+
+        assert!(setup.contact(&config, enemy, hitbox).is_empty());
+        match &setup.events()[..] {
+            [AppEvent::EntityHit {
+                target,
+                attacker,
+                damage: dealt,
+            }] => {
+                assert_eq!(*target, enemy_entity);
+                assert_eq!(*attacker, Some(player_entity));
+                assert_eq!(*dealt, damage);
+            }
+            events => panic!("Unexpected events {:?}", events),
+        }
+        assert_eq!(setup.world.read_resource::<PlayerVisibility>().noise, 0.3);
+
+        assert!(setup.contact(&config, hitbox, enemy).is_empty());
+        assert!(setup.events().is_empty());
+    }
+
+    #[test]
+    fn collect_sends_the_pickup_and_the_collector() {
+        let mut setup = Setup::new();
+        let (pickup_entity, pickup) = setup.add(ColliderObjectType::Pickup, 0.0, None);
+        let (player_entity, player) = setup.add(ColliderObjectType::Player, 5.0, None);
+
+        assert!(setup
+            .contact(&CollisionConfig::default(), player, pickup)
+            .is_empty());
+        match &setup.events()[..] {
+            [AppEvent::Collect { pickup, collector }] => {
+                assert_eq!(*pickup, pickup_entity);
+                assert_eq!(*collector, player_entity);
+            }
+            events => panic!("Unexpected events {:?}", events),
+        }
     }
 }
@@ -0,0 +1,75 @@
+//! Doors block the way until they are opened by an `OpenDoor` event. They are
+//! placed in the props layer of the map and usually opened by a trigger.
+use crate::{components::Obstacle, event::AppEvent, systems::Collider};
+use amethyst::{
+    core::{
+        shrev::{EventChannel, ReaderId},
+        SystemDesc,
+    },
+    derive::SystemDesc,
+    ecs::{
+        Component, Entities, Join, Read, ReadStorage, System, SystemData, VecStorage, World,
+        WriteStorage,
+    },
+    renderer::SpriteRender,
+};
+use log::info;
+
+#[derive(Debug, Clone, Component)]
+#[storage(VecStorage)]
+pub struct Door {
+    /// Name used in the `OpenDoor` event.
+    pub name: String,
+
+    /// Sprite to display once the door is open. Same spritesheet as the closed door.
+    pub open_sprite: Option<usize>,
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(DoorSystemDesc))]
+pub struct DoorSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<AppEvent>,
+}
+
+impl DoorSystem {
+    pub fn new(reader_id: ReaderId<AppEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for DoorSystem {
+    type SystemData = (
+        ReadStorage<'s, Door>,
+        WriteStorage<'s, Collider>,
+        WriteStorage<'s, Obstacle>,
+        WriteStorage<'s, SpriteRender>,
+        Entities<'s>,
+        Read<'s, EventChannel<AppEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (doors, mut colliders, mut obstacles, mut sprites, entities, events): Self::SystemData,
+    ) {
+        for ev in events.read(&mut self.reader_id) {
+            if let AppEvent::OpenDoor(name) = ev {
+                for (door, entity) in (&doors, &entities).join() {
+                    if door.name != *name {
+                        continue;
+                    }
+
+                    info!("Open door {}", name);
+                    // Removing the collider will remove it from the collision world as well.
+                    colliders.remove(entity);
+                    obstacles.remove(entity);
+                    if let (Some(sprite_number), Some(sprite)) =
+                        (door.open_sprite, sprites.get_mut(entity))
+                    {
+                        sprite.sprite_number = sprite_number;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Bullet emitters fire bullet patterns. The patterns are described in config/patterns.ron
+//! and compiled to a list of simple operations when the emitter is created.
+//!
+//! ```ron
+//! (
+//!     patterns: {
+//!         "double_ring": [
+//!             Ring(count: 8, bullet: (name: "enemy")),
+//!             Rotate(angle_deg: 22.5),
+//!             Wait(0.5),
+//!             Ring(count: 8, bullet: (name: "enemy", speed: 80.0)),
+//!         ],
+//!         "boss": [
+//!             Repeat(times: 3, steps: [Pattern("double_ring"), Wait(1.0)]),
+//!             Spread(count: 5, angle_deg: 60.0, aimed: true, bullet: (name: "enemy")),
+//!         ],
+//!     },
+//! )
+//! ```
+use crate::{
+    config::{BulletStep, PatternConfig, PatternStep},
+    error::{GameError, GameResult},
+    systems::{BulletSide, BulletSpawner, MyCollisionWorld, PlayerResource},
+};
+use amethyst::{
+    core::{
+        math::{Rotation2, Vector2},
+        timing::Time,
+        SystemDesc, Transform,
+    },
+    derive::SystemDesc,
+    ecs::{
+        Component, DenseVecStorage, Entities, Join, LazyUpdate, Read, ReadStorage, System,
+        SystemData, World, Write, WriteStorage,
+    },
+};
+use log::error;
+use std::{f32::consts::PI, sync::Arc};
+
+/// Compiled pattern operation.
+#[derive(Debug, Clone)]
+pub enum PatternOp {
+    /// Fire one bullet per angle (radian, relative to the emitter direction or to the
+    /// player direction if aimed).
+    Fire {
+        angles: Vec<f32>,
+        aimed: bool,
+        bullet: BulletStep,
+    },
+    /// Wait some seconds.
+    Wait(f32),
+    /// Rotate the emitter (radian).
+    Rotate(f32),
+}
+
+/// Maximum number of operations of a compiled pattern. Nested repetitions grow very fast
+/// once expanded.
+const MAX_PATTERN_OPS: usize = 10_000;
+
+/// Compile the pattern with the given name. Nested patterns and repetitions are expanded.
+pub fn compile_pattern(config: &PatternConfig, name: &str) -> GameResult<Vec<PatternOp>> {
+    let mut ops = vec![];
+    let mut stack = vec![];
+    compile_named(config, name, &mut stack, &mut ops)?;
+    Ok(ops)
+}
+
+fn compile_named(
+    config: &PatternConfig,
+    name: &str,
+    stack: &mut Vec<String>,
+    ops: &mut Vec<PatternOp>,
+) -> GameResult<()> {
+    if stack.iter().any(|n| n == name) {
+        return Err(GameError::RecursivePattern {
+            name: name.to_string(),
+        });
+    }
+
+    let steps = config
+        .patterns
+        .get(name)
+        .ok_or_else(|| GameError::PatternNotFound {
+            name: name.to_string(),
+        })?;
+    stack.push(name.to_string());
+    compile_steps(config, steps, stack, ops)?;
+    stack.pop();
+    Ok(())
+}
+
+fn compile_steps(
+    config: &PatternConfig,
+    steps: &[PatternStep],
+    stack: &mut Vec<String>,
+    ops: &mut Vec<PatternOp>,
+) -> GameResult<()> {
+    for step in steps {
+        check_length(stack, ops)?;
+        match *step {
+            PatternStep::Ring { count, ref bullet } => ops.push(PatternOp::Fire {
+                angles: (0..count)
+                    .map(|i| 2.0 * PI * i as f32 / count as f32)
+                    .collect(),
+                aimed: false,
+                bullet: bullet.clone(),
+            }),
+            PatternStep::Spread {
+                count,
+                angle,
+                aimed,
+                ref bullet,
+            } => ops.push(PatternOp::Fire {
+                angles: spread_angles(count, angle),
+                aimed,
+                bullet: bullet.clone(),
+            }),
+            PatternStep::Spiral {
+                count,
+                angle_step,
+                delay,
+                ref bullet,
+            } => {
+                for _ in 0..count {
+                    ops.push(PatternOp::Fire {
+                        angles: vec![0.0],
+                        aimed: false,
+                        bullet: bullet.clone(),
+                    });
+                    ops.push(PatternOp::Rotate(angle_step));
+                    ops.push(PatternOp::Wait(delay));
+                    check_length(stack, ops)?;
+                }
+            }
+            PatternStep::Burst {
+                count,
+                delay,
+                aimed,
+                ref bullet,
+            } => {
+                for _ in 0..count {
+                    ops.push(PatternOp::Fire {
+                        angles: vec![0.0],
+                        aimed,
+                        bullet: bullet.clone(),
+                    });
+                    ops.push(PatternOp::Wait(delay));
+                    check_length(stack, ops)?;
+                }
+            }
+            PatternStep::Wait(duration) => ops.push(PatternOp::Wait(duration)),
+            PatternStep::Rotate { angle } => ops.push(PatternOp::Rotate(angle)),
+            PatternStep::Repeat { times, ref steps } => {
+                if !steps.is_empty() {
+                    for _ in 0..times {
+                        compile_steps(config, steps, stack, ops)?;
+                    }
+                }
+            }
+            PatternStep::Pattern(ref name) => compile_named(config, name, stack, ops)?,
+        }
+    }
+
+    check_length(stack, ops)
+}
+
+fn check_length(stack: &[String], ops: &[PatternOp]) -> GameResult<()> {
+    if ops.len() > MAX_PATTERN_OPS {
+        Err(GameError::PatternTooLong {
+            name: stack.first().cloned().unwrap_or_default(),
+            max: MAX_PATTERN_OPS,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// `count` angles evenly distributed over `spread`, centered on 0.
+fn spread_angles(count: usize, spread: f32) -> Vec<f32> {
+    if count <= 1 {
+        return vec![0.0; count];
+    }
+
+    (0..count)
+        .map(|i| -spread / 2.0 + spread * i as f32 / (count - 1) as f32)
+        .collect()
+}
+
+/// Fire a bullet pattern in a loop. The bullets come from the position of the entity.
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct BulletEmitter {
+    ops: Arc<Vec<PatternOp>>,
+
+    /// Index of the next operation to execute.
+    current_op: usize,
+
+    /// Time to wait before executing the next operation.
+    wait: f32,
+
+    /// Direction of the emitter, in radian. 0 is facing down.
+    angle: f32,
+}
+
+impl BulletEmitter {
+    pub fn new(ops: Arc<Vec<PatternOp>>) -> Self {
+        Self {
+            ops,
+            current_op: 0,
+            wait: 0.0,
+            angle: 0.0,
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+pub struct EmitterSystem;
+
+impl<'s> System<'s> for EmitterSystem {
+    type SystemData = (
+        WriteStorage<'s, BulletEmitter>,
+        ReadStorage<'s, Transform>,
+        Read<'s, PlayerResource>,
+        Read<'s, Time>,
+        Entities<'s>,
+        Read<'s, LazyUpdate>,
+        Read<'s, BulletSpawner>,
+        Write<'s, MyCollisionWorld>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut emitters,
+            transforms,
+            player,
+            time,
+            entities,
+            updater,
+            bullet_spawner,
+            mut collision,
+        ): Self::SystemData,
+    ) {
+        let player_position = player
+            .player
+            .and_then(|e| transforms.get(e))
+            .map(|t| t.translation().xy());
+
+        for (emitter, t) in (&mut emitters, &transforms).join() {
+            if emitter.ops.is_empty() {
+                continue;
+            }
+
+            emitter.wait -= time.delta_seconds();
+            // A pattern without wait should not block the game so at most one loop of the
+            // pattern is executed per frame.
+            let mut nb_executed = 0;
+            while emitter.wait <= 0.0 && nb_executed < emitter.ops.len() {
+                let ops = Arc::clone(&emitter.ops);
+                match ops[emitter.current_op] {
+                    PatternOp::Wait(duration) => emitter.wait += duration,
+                    PatternOp::Rotate(angle) => emitter.angle += angle,
+                    PatternOp::Fire {
+                        ref angles,
+                        aimed,
+                        ref bullet,
+                    } => {
+                        let origin = *t.translation();
+                        let aim = player_position
+                            .map(|position| position - origin.xy())
+                            .filter(|d| aimed && d.norm() > std::f32::EPSILON);
+                        let base_direction = match aim {
+                            Some(d) => d.normalize(),
+                            None => Rotation2::new(emitter.angle) * Vector2::new(0.0, -1.0),
+                        };
+
+                        for angle in angles {
+                            if let Err(e) = bullet_spawner.spawn_bullet(
+                                &entities,
+                                &updater,
+                                &mut collision,
+                                &bullet.name,
+                                origin,
+                                Rotation2::new(*angle) * base_direction,
+                                BulletSide::Enemy,
+                                bullet.speed,
+                                bullet.behavior,
+                            ) {
+                                error!("Emitter cannot spawn bullet: {}", e);
+                            }
+                        }
+                    }
+                }
+
+                emitter.current_op = (emitter.current_op + 1) % emitter.ops.len();
+                nb_executed += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(patterns: &str) -> PatternConfig {
+        ron::de::from_str(patterns).expect("Invalid pattern config")
+    }
+
+    #[test]
+    fn recursive_pattern_is_an_error() {
+        let config = config(
+            r#"(patterns: {
+                "a": [Wait(1.0), Pattern("b")],
+                "b": [Pattern("a")],
+            })"#,
+        );
+        match compile_pattern(&config, "a") {
+            Err(GameError::RecursivePattern { name }) => assert_eq!(name, "a"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pattern_can_be_used_several_times() {
+        let config = config(
+            r#"(patterns: {
+                "wait": [Wait(1.0)],
+                "twice": [Pattern("wait"), Pattern("wait")],
+            })"#,
+        );
+        assert_eq!(compile_pattern(&config, "twice").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn unknown_pattern_is_an_error() {
+        let config = config(r#"(patterns: { "a": [Pattern("missing")] })"#);
+        match compile_pattern(&config, "unknown") {
+            Err(GameError::PatternNotFound { name }) => assert_eq!(name, "unknown"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        match compile_pattern(&config, "a") {
+            Err(GameError::PatternNotFound { name }) => assert_eq!(name, "missing"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn repeat_is_expanded() {
+        let config = config(
+            r#"(patterns: {
+                "a": [Repeat(times: 3, steps: [Wait(1.0), Rotate(angle_deg: 90.0)])],
+            })"#,
+        );
+        let ops = compile_pattern(&config, "a").unwrap();
+        assert_eq!(ops.len(), 6);
+        match ops[1] {
+            PatternOp::Rotate(angle) => assert_eq!(angle, 90.0f32.to_radians()),
+            ref op => panic!("Unexpected operation {:?}", op),
+        }
+    }
+
+    #[test]
+    fn nested_repeat_is_limited() {
+        let config = config(
+            r#"(patterns: {
+                "a": [Repeat(times: 1000, steps: [Repeat(times: 1000, steps: [Wait(1.0)])])],
+            })"#,
+        );
+        match compile_pattern(&config, "a") {
+            Err(GameError::PatternTooLong { name, max }) => {
+                assert_eq!(name, "a");
+                assert_eq!(max, MAX_PATTERN_OPS);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn single_bullet_is_not_spread() {
+        assert_eq!(spread_angles(1, PI), vec![0.0]);
+        assert!(spread_angles(0, PI).is_empty());
+    }
+
+    #[test]
+    fn spread_goes_from_one_end_to_the_other() {
+        assert_eq!(spread_angles(2, PI), vec![-PI / 2.0, PI / 2.0]);
+
+        let angles = spread_angles(5, 1.0);
+        assert_eq!(angles.len(), 5);
+        assert_eq!(angles[0], -0.5);
+        assert_eq!(angles[2], 0.0);
+        assert_eq!(angles[4], 0.5);
+    }
+}
@@ -1,31 +1,35 @@
 //! System that control the enemies
 //!
 use amethyst::{
-    core::{math::Vector3, timing::Time, SystemDesc, Transform},
+    core::{
+        math::{Vector2, Vector3},
+        shrev::EventChannel,
+        timing::Time,
+        SystemDesc, Transform,
+    },
     derive::SystemDesc,
     ecs::{
-        Component, Entities, Join, LazyUpdate, Read, System, SystemData, VecStorage, World, Write,
-        WriteStorage,
+        Component, Entities, Join, LazyUpdate, Read, ReadStorage, System, SystemData, VecStorage,
+        World, Write, WriteStorage,
     },
 };
 
 use crate::objects::enemy::{CreepyFirstBoss, SimpleEnemy};
 use crate::{
-    config::EnemyConfig,
-    systems::{AnimationController, BulletSpawner, MyCollisionWorld, PlayerResource},
+    components::Obstacle,
+    config::EnemyBehavior,
+    event::AppEvent,
+    systems::{
+        movement::Velocity, visibility::PlayerVisibility, AnimationController, BulletSpawner,
+        Collider, Grounded, MyCollisionWorld, PlayerResource,
+    },
+    vision::LineOfSight,
 };
 
 #[allow(unused_imports)]
 use log::{error, info};
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub enum EnemyType {
-    /// Most basic enemy. Follow and shoot.
-    Simple,
-    CreepyFirstBoss,
-}
-
-#[derive(Debug, Clone, Copy, Component)]
+#[derive(Debug, Clone, Component)]
 #[storage(VecStorage)]
 pub enum Enemy {
     Simple(SimpleEnemy),
@@ -33,40 +37,82 @@ pub enum Enemy {
 }
 
 impl Enemy {
-    pub fn from_config(enemy_type: EnemyType, config: &EnemyConfig) -> Self {
-        match enemy_type {
-            EnemyType::Simple => Enemy::Simple(SimpleEnemy::from_config(&config.simple_enemy)),
-            EnemyType::CreepyFirstBoss => Enemy::CreepyFirstBoss(CreepyFirstBoss::default()),
+    pub fn from_behavior(behavior: &EnemyBehavior) -> Self {
+        match behavior {
+            EnemyBehavior::Simple(config) => Enemy::Simple(SimpleEnemy::from_config(config)),
+            EnemyBehavior::CreepyFirstBoss { .. } => Enemy::CreepyFirstBoss(CreepyFirstBoss),
         }
     }
 
-    /// What to do every frame
+    /// Whether the enemy can see the player. Bosses always know where the player is.
+    pub fn can_see(
+        &self,
+        t: &Transform,
+        player_vec: &Vector3<f32>,
+        visibility: &PlayerVisibility,
+        line_of_sight: &LineOfSight,
+    ) -> bool {
+        match *self {
+            Enemy::Simple(ref simple_enemy) => {
+                simple_enemy.can_see(t, player_vec, visibility, line_of_sight)
+            }
+            Enemy::CreepyFirstBoss(_) => true,
+        }
+    }
+
+    /// Whether the enemy can be killed by a silent takedown. Bosses are always on their
+    /// guard.
+    pub fn can_be_taken_down(
+        &self,
+        t: &Transform,
+        attacker_position: Vector2<f32>,
+        max_angle: f32,
+    ) -> bool {
+        match *self {
+            Enemy::Simple(ref simple_enemy) => {
+                simple_enemy.can_be_taken_down(t, attacker_position, max_angle)
+            }
+            Enemy::CreepyFirstBoss(_) => false,
+        }
+    }
+
+    /// The alarm has been raised because the player has been seen at `target`.
+    pub fn alert(&mut self, target: Vector2<f32>) {
+        if let Enemy::Simple(ref mut simple_enemy) = *self {
+            simple_enemy.alert(target);
+        }
+    }
+
+    /// What to do every frame. Can return an event to send (alarm...)
     pub fn update(
         &mut self,
         delta_time: f32,
         t: &mut Transform,
+        velocity: &mut Velocity,
         animations: &mut Option<&mut AnimationController>,
 
         player_vec: &Vector3<f32>,
+        player_visible: bool,
         bullet_spawner: &Read<BulletSpawner>,
         entities: &Entities,
         updater: &Read<LazyUpdate>,
         collision: &mut Write<MyCollisionWorld>,
-    ) {
+    ) -> Option<AppEvent> {
         match *self {
             Enemy::Simple(ref mut simple_enemy) => simple_enemy.update(
                 delta_time,
                 t,
+                velocity,
                 animations,
                 player_vec,
+                player_visible,
                 bullet_spawner,
                 entities,
                 updater,
                 collision,
             ),
-            Enemy::CreepyFirstBoss(ref mut creepy_boss) => {
-                creepy_boss.update(delta_time, t, bullet_spawner, entities, updater, collision)
-            }
+            // The boss does not move and its bullets are fired by its emitter.
+            Enemy::CreepyFirstBoss(_) => None,
         }
     }
 }
@@ -91,6 +137,12 @@ impl<'s> System<'s> for EnemySystem {
         Read<'s, BulletSpawner>,
         Write<'s, MyCollisionWorld>,
         WriteStorage<'s, AnimationController>,
+        ReadStorage<'s, Collider>,
+        ReadStorage<'s, Obstacle>,
+        Write<'s, EventChannel<AppEvent>>,
+        Read<'s, PlayerVisibility>,
+        ReadStorage<'s, Grounded>,
+        WriteStorage<'s, Velocity>,
     );
 
     fn run(
@@ -105,26 +157,48 @@ impl<'s> System<'s> for EnemySystem {
             bullet_spawner,
             mut collision,
             mut animations,
+            colliders,
+            obstacles,
+            mut events,
+            visibility,
+            grounded,
+            mut velocities,
         ): Self::SystemData,
     ) {
+        let mut events_to_send = vec![];
         if let Some(e) = player.player {
             if let Some(player_transform) = transforms.get(e).cloned() {
                 let player_vec = player_transform.translation();
 
-                for (t, enemy, e) in (&mut transforms, &mut enemies, &entities).join() {
-                    enemy.update(
+                for (t, enemy, velocity, e) in
+                    (&mut transforms, &mut enemies, &mut velocities, &entities).join()
+                {
+                    // Falling enemies cannot do anything anymore.
+                    if grounded.get(e).map_or(false, |g| g.is_falling()) {
+                        continue;
+                    }
+
+                    let player_visible = {
+                        let line_of_sight = LineOfSight::new(&obstacles, &colliders, &collision);
+                        enemy.can_see(t, &player_vec, &visibility, &line_of_sight)
+                    };
+                    let maybe_event = enemy.update(
                         time.delta_seconds(),
                         t,
+                        velocity,
                         &mut animations.get_mut(e),
                         &player_vec,
+                        player_visible,
                         &bullet_spawner,
                         &entities,
                         &updater,
                         &mut collision,
                     );
+                    events_to_send.extend(maybe_event);
                 }
             }
         }
         // info!("Processed data for {} enemies", i);
+        events.drain_vec_write(&mut events_to_send);
     }
 }
@@ -1,21 +1,16 @@
-//! Cleanup entities with their collider.
-//! Deleting an entity requires to also delete its collider if attached.
-//! This involves multiple systems so to avoid cluttering the systems,
-//! entity deletion will be mostly done here when receiving events.
+//! Cleanup entities when receiving events.
+//! The collision objects of the deleted entities are removed by the
+//! collision system so there is nothing else to do here.
 //!
 //!
-use crate::{
-    event::AppEvent,
-    systems::{Collider, MyCollisionWorld},
-    util::delete_entity_with_collider,
-};
+use crate::event::AppEvent;
 use amethyst::{
     core::{
         shrev::{EventChannel, ReaderId},
         SystemDesc,
     },
     derive::SystemDesc,
-    ecs::{Entities, Entity, Read, ReadStorage, System, SystemData, World, Write},
+    ecs::{Entities, Entity, Read, System, SystemData, World},
 };
 #[allow(unused_imports)]
 use log::error;
@@ -32,32 +27,21 @@ impl GarbageSystem {
         Self { reader_id }
     }
 
-    /// will destroy the entity and its colliders.
-    fn boum(
-        &self,
-        entity: Entity,
-        entities: &Entities,
-        collision_world: &mut MyCollisionWorld,
-        colliders: &ReadStorage<Collider>,
-    ) {
-        delete_entity_with_collider(entity, colliders, entities, &mut collision_world.world);
+    /// will destroy the entity.
+    fn boum(&self, entity: Entity, entities: &Entities) {
+        if let Err(e) = entities.delete(entity) {
+            error!("{}", e);
+        }
     }
 }
 
 impl<'s> System<'s> for GarbageSystem {
-    type SystemData = (
-        Entities<'s>,
-        Write<'s, MyCollisionWorld>,
-        Read<'s, EventChannel<AppEvent>>,
-        ReadStorage<'s, Collider>,
-    );
+    type SystemData = (Entities<'s>, Read<'s, EventChannel<AppEvent>>);
 
-    fn run(&mut self, (entities, mut collision_world, events, colliders): Self::SystemData) {
+    fn run(&mut self, (entities, events): Self::SystemData) {
         for ev in events.read(&mut self.reader_id) {
             match ev {
-                AppEvent::EnemyDied(e) => {
-                    self.boum(*e, &entities, &mut collision_world, &colliders)
-                }
+                AppEvent::EnemyDied(e) => self.boum(*e, &entities),
                 _ => (),
             }
         }
@@ -5,17 +5,78 @@ use amethyst::{
         SystemDesc,
     },
     derive::SystemDesc,
-    ecs::{Component, Entity, Read, System, SystemData, VecStorage, World, Write, WriteStorage},
+    ecs::{
+        Component, Entities, Entity, Join, Read, System, SystemData, VecStorage, World, Write,
+        WriteStorage,
+    },
+    renderer::Hidden,
 };
 
 use crate::{
     event::AppEvent,
     systems::{Player, PlayerStatus},
 };
-use log::debug;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Time during which an invulnerable entity is hidden, then visible, in seconds.
+const BLINK_PERIOD: f32 = 0.1;
+
+/// Kind of damage. Entities can resist or be weak against some types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Ice,
+    Magic,
+}
+
+impl Default for DamageType {
+    fn default() -> Self {
+        DamageType::Physical
+    }
+}
+
+/// Damage dealt by a hit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Damage {
+    pub amount: i32,
+    #[serde(default)]
+    pub ty: DamageType,
+
+    /// Kill the entity whatever its health and resistances (takedowns...)
+    #[serde(default)]
+    pub lethal: bool,
+}
+
+impl Damage {
+    pub fn new(amount: i32, ty: DamageType) -> Self {
+        Self {
+            amount,
+            ty,
+            lethal: false,
+        }
+    }
+
+    pub fn lethal() -> Self {
+        Self {
+            lethal: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Self::new(1, DamageType::Physical)
+    }
+}
+
 /// Health - when reach 0, the entity is removed.
-/// Health is an integer. A hit will always remove an entire portion of health
-#[derive(Debug, Clone, Copy, Component)]
+/// Health is an integer. The damage of a hit is multiplied by the resistance of the entity
+/// to the damage type.
+#[derive(Debug, Clone, Component)]
 #[storage(VecStorage)]
 pub struct Health {
     /// Current value of health.
@@ -23,6 +84,16 @@ pub struct Health {
 
     /// Maximum value of health.
     max_health: i32,
+
+    /// Damage multiplier per damage type. Lower than 1 is a resistance, higher than 1 is a
+    /// weakness. Types that are not in the map deal normal damage.
+    resistances: HashMap<DamageType, f32>,
+
+    /// How long the entity ignores the hits after being damaged, in seconds.
+    invulnerability: f32,
+
+    /// Time left before the entity can be damaged again. The entity blinks meanwhile.
+    invulnerable_time: f32,
 }
 
 impl Health {
@@ -31,7 +102,60 @@ impl Health {
         Self {
             current_health: max_health,
             max_health,
+            resistances: HashMap::new(),
+            invulnerability: 0.0,
+            invulnerable_time: 0.0,
+        }
+    }
+
+    pub fn with_resistances(mut self, resistances: HashMap<DamageType, f32>) -> Self {
+        self.resistances = resistances;
+        self
+    }
+
+    pub fn with_invulnerability(mut self, invulnerability: f32) -> Self {
+        self.invulnerability = invulnerability;
+        self
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_time > 0.0
+    }
+
+    /// Health removed by the given damage once the resistances are applied.
+    pub fn damage_taken(&self, damage: Damage) -> i32 {
+        if damage.lethal {
+            return self.current_health;
         }
+
+        let multiplier = self.resistances.get(&damage.ty).cloned().unwrap_or(1.0);
+        ((damage.amount as f32 * multiplier).round() as i32).max(0)
+    }
+
+    /// Apply a hit to the entity. Return true if this hit killed it.
+    pub fn hit(&mut self, damage: Damage) -> bool {
+        // Several hits can arrive in the same frame. Only the first one kills.
+        if self.current_health <= 0 {
+            debug!("Entity is already dead, ignore hit");
+            return false;
+        }
+
+        if self.is_invulnerable() && !damage.lethal {
+            debug!("Entity is invulnerable, ignore hit");
+            return false;
+        }
+
+        let damage_taken = self.damage_taken(damage);
+        self.current_health -= damage_taken;
+        if damage_taken > 0 {
+            self.invulnerable_time = self.invulnerability;
+        }
+        self.current_health <= 0
+    }
+
+    /// Count down the invulnerability.
+    pub fn cool_down(&mut self, delta_time: f32) {
+        self.invulnerable_time = (self.invulnerable_time - delta_time).max(0.0);
     }
 }
 
@@ -55,14 +179,14 @@ impl HealthSystem {
     fn process_hit(
         &self,
         entity: Entity,
+        damage: Damage,
         health_storage: &mut WriteStorage<Health>,
         players: &mut WriteStorage<Player>,
     ) -> Vec<AppEvent> {
         let mut to_send = vec![];
-        debug!("Process Hit for entity {:?}", entity);
+        debug!("Process Hit for entity {:?} ({:?})", entity, damage);
         if let Some(ref mut h) = health_storage.get_mut(entity) {
-            h.current_health -= 1;
-            if h.current_health <= 0 {
+            if h.hit(damage) {
                 debug!("Entity died :(");
 
                 // let's check if that is the player :)
@@ -86,17 +210,39 @@ impl<'s> System<'s> for HealthSystem {
     type SystemData = (
         WriteStorage<'s, Health>,
         WriteStorage<'s, Player>,
+        WriteStorage<'s, Hidden>,
+        Entities<'s>,
         Read<'s, Time>,
         Write<'s, EventChannel<AppEvent>>,
     );
 
-    fn run(&mut self, (mut healths, mut players, _time, mut events): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut healths, mut players, mut hiddens, entities, time, mut events): Self::SystemData,
+    ) {
+        // Blink while invulnerable.
+        let delta_time = time.delta_seconds();
+        for (health, entity) in (&mut healths, &entities).join() {
+            if health.invulnerable_time > 0.0 {
+                health.cool_down(delta_time);
+                let hidden = health.invulnerable_time > 0.0
+                    && (health.invulnerable_time / BLINK_PERIOD) as i32 % 2 == 0;
+                if hidden {
+                    if let Err(e) = hiddens.insert(entity, Hidden) {
+                        error!("{}", e);
+                    }
+                } else {
+                    hiddens.remove(entity);
+                }
+            }
+        }
+
         let mut events_to_send = vec![];
         for ev in events.read(&mut self.reader_id) {
-            if let AppEvent::EntityHit(e) = ev {
+            if let AppEvent::EntityHit { target, damage, .. } = ev {
                 // In that case, one entity has been hit by a bullet so let's check if it has
                 // some health component.
-                let mut to_send = self.process_hit(*e, &mut healths, &mut players);
+                let mut to_send = self.process_hit(*target, *damage, &mut healths, &mut players);
                 events_to_send.append(&mut to_send);
             }
         }
@@ -104,3 +250,95 @@ impl<'s> System<'s> for HealthSystem {
         events.drain_vec_write(&mut events_to_send);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::{Builder, WorldExt};
+
+    fn resistant() -> Health {
+        let mut resistances = HashMap::new();
+        resistances.insert(DamageType::Fire, 0.5);
+        resistances.insert(DamageType::Ice, 2.0);
+        resistances.insert(DamageType::Magic, 0.0);
+        Health::new(10).with_resistances(resistances)
+    }
+
+    #[test]
+    fn resistances_scale_the_damage() {
+        let health = resistant();
+        assert_eq!(health.damage_taken(Damage::new(3, DamageType::Fire)), 2);
+        assert_eq!(health.damage_taken(Damage::new(3, DamageType::Ice)), 6);
+        assert_eq!(health.damage_taken(Damage::new(3, DamageType::Magic)), 0);
+        assert_eq!(health.damage_taken(Damage::new(3, DamageType::Physical)), 3);
+    }
+
+    #[test]
+    fn lethal_damage_ignores_resistances() {
+        let mut health = resistant();
+        health.hit(Damage::new(3, DamageType::Physical));
+        assert_eq!(health.damage_taken(Damage::lethal()), 7);
+    }
+
+    #[test]
+    fn invulnerability_ignores_hits_until_it_wears_off() {
+        let mut health = Health::new(10).with_invulnerability(1.0);
+        assert!(!health.hit(Damage::new(3, DamageType::Physical)));
+        assert!(health.is_invulnerable());
+
+        health.hit(Damage::new(3, DamageType::Physical));
+        assert_eq!(health.current_health, 7);
+
+        health.cool_down(0.6);
+        assert!(health.is_invulnerable());
+        health.cool_down(0.6);
+        assert!(!health.is_invulnerable());
+
+        health.hit(Damage::new(3, DamageType::Physical));
+        assert_eq!(health.current_health, 4);
+    }
+
+    #[test]
+    fn resisted_hits_do_not_make_invulnerable() {
+        let mut health = resistant().with_invulnerability(1.0);
+        health.hit(Damage::new(3, DamageType::Magic));
+        assert!(!health.is_invulnerable());
+    }
+
+    #[test]
+    fn lethal_damage_bypasses_invulnerability() {
+        let mut health = Health::new(10).with_invulnerability(1.0);
+        health.hit(Damage::new(3, DamageType::Physical));
+        assert!(health.is_invulnerable());
+        assert!(health.hit(Damage::lethal()));
+        assert_eq!(health.current_health, 0);
+    }
+
+    #[test]
+    fn dead_entities_ignore_hits() {
+        let mut health = Health::new(2);
+        assert!(health.hit(Damage::new(5, DamageType::Physical)));
+        assert!(!health.hit(Damage::new(5, DamageType::Physical)));
+        assert!(!health.hit(Damage::lethal()));
+        assert_eq!(health.current_health, -3);
+    }
+
+    #[test]
+    fn only_the_first_killing_hit_sends_an_event() {
+        let mut world = World::new();
+        world.register::<Health>();
+        world.register::<Player>();
+        let enemy = world.create_entity().with(Health::new(1)).build();
+        let system = HealthSystem::new(EventChannel::<AppEvent>::new().register_reader());
+
+        let (mut healths, mut players) =
+            world.system_data::<(WriteStorage<Health>, WriteStorage<Player>)>();
+        let first = system.process_hit(enemy, Damage::default(), &mut healths, &mut players);
+        let second = system.process_hit(enemy, Damage::default(), &mut healths, &mut players);
+        match first.as_slice() {
+            [AppEvent::EnemyDied(e)] => assert_eq!(*e, enemy),
+            events => panic!("Expected EnemyDied, got {:?}", events),
+        }
+        assert!(second.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+//! Information displayed on top of the game (player visibility...)
+use amethyst::{
+    assets::Loader,
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::{
+        Component, Entity, Join, NullStorage, Read, ReadStorage, System, SystemData, World,
+        WriteStorage,
+    },
+    prelude::*,
+    ui::{Anchor, TtfFormat, UiText, UiTransform},
+};
+
+use crate::systems::visibility::PlayerVisibility;
+
+/// Tag for the text that displays the visibility of the player.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct VisibilityText;
+
+/// Create the HUD entities. The returned entity should be deleted when leaving the game.
+pub fn create_hud(world: &mut World) -> Entity {
+    let font = world.read_resource::<Loader>().load(
+        "font/square.ttf",
+        TtfFormat,
+        (),
+        &world.read_resource(),
+    );
+    let text = UiText::new(font, visibility_text(0.0), [1.0, 1.0, 1.0, 1.0], 25.);
+    let transform = UiTransform::new(
+        "visibility".to_string(),
+        Anchor::TopLeft,
+        Anchor::TopLeft,
+        10.,
+        -10.,
+        90.,
+        300.,
+        25.,
+    );
+
+    world
+        .create_entity()
+        .with(transform)
+        .with(text)
+        .with(VisibilityText)
+        .build()
+}
+
+fn visibility_text(score: f32) -> String {
+    format!("Visibility: {:>3}%", (score * 100.0).round() as i32)
+}
+
+/// Update the HUD texts every frame.
+#[derive(SystemDesc)]
+pub struct HudSystem;
+
+impl<'s> System<'s> for HudSystem {
+    type SystemData = (
+        ReadStorage<'s, VisibilityText>,
+        WriteStorage<'s, UiText>,
+        Read<'s, PlayerVisibility>,
+    );
+
+    fn run(&mut self, (visibility_texts, mut texts, visibility): Self::SystemData) {
+        for (_, text) in (&visibility_texts, &mut texts).join() {
+            text.text = visibility_text(visibility.score);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Close combat attack of the player. The attack creates a short lived hitbox in front of
+//! the player. Enemies that do not know the player is around can be taken down silently
+//! from behind.
+//!
+//! The hits are resolved by the collision system (see the `Melee` responses in
+//! collision.ron).
+use crate::{
+    config::PlayerConfig,
+    systems::{
+        aim::PlayerAim, health::Damage, visibility::PlayerVisibility, Collider,
+        ColliderObjectType, Enemy, MyCollisionWorld, Player, PlayerStatus,
+    },
+};
+use amethyst::{
+    core::{
+        math::{Vector2, Vector3},
+        timing::Time,
+        SystemDesc, Transform,
+    },
+    derive::SystemDesc,
+    ecs::{
+        Component, DenseVecStorage, Entities, Entity, Join, LazyUpdate, Read, ReadStorage,
+        System, SystemData, VecStorage, World, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+};
+use log::{debug, error, info};
+
+/// Attached to the player. Keeps track of the time before the next melee attack.
+#[derive(Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct MeleeAttack {
+    cooldown: f32,
+}
+
+/// Hitbox of a melee attack. It hits every enemy it touches once and disappears after its
+/// lifetime.
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct MeleeHitbox {
+    /// Entity that attacks.
+    pub attacker: Entity,
+
+    /// Where the attacker was when attacking. Used to check whether the enemy is attacked
+    /// from behind.
+    pub origin: Vector2<f32>,
+
+    pub damage: Damage,
+
+    /// Noise made when hitting an enemy that is not taken down.
+    pub noise: f32,
+
+    /// Angle in radians behind the enemy from which a takedown is possible.
+    pub takedown_angle: f32,
+
+    /// Time left before the hitbox is removed.
+    pub lifetime: f32,
+
+    /// Enemies already hit by this attack.
+    pub hit_entities: Vec<Entity>,
+}
+
+impl MeleeHitbox {
+    /// Damage dealt to `target`, or None if this attack has already hit it. Enemies that
+    /// can be taken down are killed silently, the other hits make noise.
+    pub fn strike(
+        &mut self,
+        target: Entity,
+        enemy: Option<&Enemy>,
+        transform: Option<&Transform>,
+        visibility: &mut PlayerVisibility,
+    ) -> Option<Damage> {
+        if self.hit_entities.contains(&target) {
+            return None;
+        }
+        self.hit_entities.push(target);
+
+        let taken_down = match (enemy, transform) {
+            (Some(enemy), Some(t)) => enemy.can_be_taken_down(t, self.origin, self.takedown_angle),
+            _ => false,
+        };
+        if taken_down {
+            info!("Silent takedown!");
+            Some(Damage::lethal())
+        } else {
+            visibility.add_noise(self.noise);
+            Some(self.damage)
+        }
+    }
+}
+
+#[derive(SystemDesc)]
+pub struct MeleeSystem;
+
+impl<'s> System<'s> for MeleeSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, MeleeAttack>,
+        WriteStorage<'s, MeleeHitbox>,
+        ReadStorage<'s, Transform>,
+        Entities<'s>,
+        Read<'s, LazyUpdate>,
+        Write<'s, MyCollisionWorld>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, PlayerAim>,
+        Read<'s, PlayerConfig>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            players,
+            mut melee_attacks,
+            mut hitboxes,
+            transforms,
+            entities,
+            updater,
+            mut collision,
+            input,
+            aim,
+            player_config,
+            time,
+        ): Self::SystemData,
+    ) {
+        let config = player_config.melee;
+        let delta_time = time.delta_seconds();
+
+        // Start a new attack.
+        for (player, melee, transform, entity) in
+            (&players, &mut melee_attacks, &transforms, &entities).join()
+        {
+            melee.cooldown = (melee.cooldown - delta_time).max(0.0);
+            let can_attack = match player.state {
+                PlayerStatus::Walking => melee.cooldown <= 0.0,
+                _ => false,
+            };
+            if can_attack && input.action_is_down("melee").unwrap_or(false) {
+                melee.cooldown = config.cooldown;
+                let origin = transform.translation().xy();
+                let position = origin + aim.direction * config.range;
+                debug!("Melee attack at {:?}", position);
+
+                let hitbox = entities.create();
+                let collider = Collider::new_rect(
+                    position,
+                    config.size,
+                    config.size,
+                    &mut collision.world,
+                    ColliderObjectType::Melee,
+                    Some(&[ColliderObjectType::Enemy]),
+                    Some(hitbox),
+                );
+                let mut t = Transform::default();
+                t.set_translation(Vector3::new(position.x, position.y, 0.0));
+                updater.insert(hitbox, t);
+                updater.insert(hitbox, collider);
+                updater.insert(
+                    hitbox,
+                    MeleeHitbox {
+                        attacker: entity,
+                        origin,
+                        damage: config.damage,
+                        noise: config.noise,
+                        takedown_angle: config.takedown_angle,
+                        lifetime: config.duration,
+                        hit_entities: vec![],
+                    },
+                );
+            }
+        }
+
+        for (hitbox, entity) in (&mut hitboxes, &entities).join() {
+            hitbox.lifetime -= delta_time;
+            if hitbox.lifetime <= 0.0 {
+                // The collision object is removed with the entity.
+                if let Err(e) = entities.delete(entity) {
+                    error!("{}", e);
+                }
+            }
+        }
+    }
+}
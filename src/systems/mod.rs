@@ -5,15 +5,24 @@ pub use bullet::*;
 pub use collision::*;
 pub use enemy::{Enemy, EnemySystem};
 
+pub mod aim;
+pub mod alarm;
 pub mod animation;
 pub mod attack;
 pub mod bullet;
 mod collision;
 mod dialog;
+pub mod door;
+pub mod emitter;
 pub mod enemy;
 pub mod garbage;
 pub mod health;
+pub mod hud;
+pub mod melee;
+pub mod movement;
+pub mod pickup;
 mod player;
 pub mod schedule;
 pub mod spawn;
+pub mod visibility;
 pub mod wave;
@@ -0,0 +1,187 @@
+//! Movement of the entities that walk or can be pushed around. The systems that control
+//! the entities (player, enemies...) only set the velocity. It is applied here every frame
+//! without going through the walls.
+//!
+//! Bullets push back the entities they hit (see the `knockback` of the bullet archetypes).
+//! The bullets themselves are moved by the bullet system because they bounce off the walls
+//! or hit them instead of sliding along them.
+use crate::{
+    event::AppEvent,
+    systems::{Bullet, Collider, Grounded, MyCollisionWorld, Player, PlayerStatus},
+};
+use amethyst::{
+    core::{
+        math::Vector2,
+        shrev::{EventChannel, ReaderId},
+        timing::Time,
+        SystemDesc, Transform,
+    },
+    derive::SystemDesc,
+    ecs::{
+        Component, DenseVecStorage, Join, Read, ReadStorage, System, SystemData, World,
+        WriteStorage,
+    },
+};
+use log::debug;
+
+/// How fast the impulses go back to zero.
+const DAMPING: f32 = 8.0;
+
+/// Below that speed (unit/sec), the impulses stop.
+const MIN_SPEED: f32 = 1.0;
+
+/// Speed of the entity, in unit/sec.
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct Velocity {
+    /// Where the entity wants to go (walking...). It has to be set every frame, it is reset
+    /// once applied.
+    pub desired: Vector2<f32>,
+
+    /// Speed given by sudden pushes (knockback...). It goes back to zero over time.
+    pub impulse: Vector2<f32>,
+
+    /// Multiplier of the knockback received. 0 cannot be pushed.
+    pub knockback: f32,
+
+    /// Movement done by walking during the last frame, once the walls and the edges are taken
+    /// into account. The walk animations follow it.
+    pub walked: Vector2<f32>,
+}
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Self {
+            desired: Vector2::zeros(),
+            impulse: Vector2::zeros(),
+            knockback: 1.0,
+            walked: Vector2::zeros(),
+        }
+    }
+}
+
+impl Velocity {
+    pub fn with_knockback(mut self, knockback: f32) -> Self {
+        self.knockback = knockback;
+        self
+    }
+
+    /// Add a sudden change of speed.
+    pub fn apply_impulse(&mut self, impulse: Vector2<f32>) {
+        self.impulse += impulse * self.knockback;
+    }
+}
+
+/// The player can only be pushed while walking. It cannot be stopped during a dash and
+/// should not move while falling.
+fn can_be_pushed(player: Option<&Player>) -> bool {
+    match player.map(|p| p.state) {
+        None | Some(PlayerStatus::Walking) => true,
+        _ => false,
+    }
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(MovementSystemDesc))]
+pub struct MovementSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<AppEvent>,
+}
+
+impl MovementSystem {
+    pub fn new(reader_id: ReaderId<AppEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for MovementSystem {
+    type SystemData = (
+        WriteStorage<'s, Velocity>,
+        WriteStorage<'s, Transform>,
+        ReadStorage<'s, Collider>,
+        ReadStorage<'s, Bullet>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Grounded>,
+        Read<'s, MyCollisionWorld>,
+        Read<'s, Time>,
+        Read<'s, EventChannel<AppEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut velocities,
+            mut transforms,
+            colliders,
+            bullets,
+            players,
+            grounded,
+            collision,
+            time,
+            events,
+        ): Self::SystemData,
+    ) {
+        // Push back the entities hit by a bullet.
+        for ev in events.read(&mut self.reader_id) {
+            if let AppEvent::EntityHit {
+                target,
+                attacker: Some(attacker),
+                ..
+            } = ev
+            {
+                if !can_be_pushed(players.get(*target)) {
+                    continue;
+                }
+                if let (Some(bullet), Some(velocity)) =
+                    (bullets.get(*attacker), velocities.get_mut(*target))
+                {
+                    debug!("Knockback {:?}", bullet.direction * bullet.knockback);
+                    velocity.apply_impulse(bullet.direction * bullet.knockback);
+                }
+            }
+        }
+
+        let delta_time = time.delta_seconds();
+        for (velocity, transform, collider, player, grounded) in (
+            &mut velocities,
+            &mut transforms,
+            &colliders,
+            players.maybe(),
+            grounded.maybe(),
+        )
+            .join()
+        {
+            if !can_be_pushed(player) {
+                velocity.impulse = Vector2::zeros();
+            }
+
+            let position = transform.translation().xy();
+            let mut new_position = position;
+            if velocity.desired.norm_squared() > 0.0 {
+                let walked =
+                    collision.move_and_slide(collider, position, velocity.desired * delta_time);
+                // Grounded entities do not walk into the pits on their own. If they are not
+                // on the ground already, there is no edge to stop at.
+                let stop_at_edge = grounded.is_some()
+                    && !collision.is_on_walkable(collider, walked)
+                    && collision.is_on_walkable(collider, position);
+                if !stop_at_edge {
+                    new_position = walked;
+                }
+                velocity.desired = Vector2::zeros();
+            }
+            velocity.walked = new_position - position;
+
+            if velocity.impulse.norm() < MIN_SPEED {
+                velocity.impulse = Vector2::zeros();
+            } else {
+                new_position =
+                    collision.move_and_slide(collider, new_position, velocity.impulse * delta_time);
+                velocity.impulse *= (-DAMPING * delta_time).exp();
+            }
+
+            transform.set_translation_x(new_position.x);
+            transform.set_translation_y(new_position.y);
+        }
+    }
+}
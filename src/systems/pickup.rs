@@ -0,0 +1,84 @@
+//! Objects that the player can collect. They are placed on the map or dropped by the
+//! enemies. The collision system sends a `Collect` event when the player touches a pickup.
+use crate::{
+    config::{DropConfig, WeaponConfig},
+    event::AppEvent,
+    systems::attack::Inventory,
+};
+use amethyst::{
+    core::{
+        shrev::{EventChannel, ReaderId},
+        SystemDesc,
+    },
+    derive::SystemDesc,
+    ecs::{
+        Component, DenseVecStorage, Entities, Read, ReadStorage, System, SystemData, World,
+        WriteStorage,
+    },
+};
+use log::{error, info};
+
+/// Give a new weapon (or its ammo) to the player. The collider of the entity should have
+/// the `Pickup` type.
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct WeaponPickup {
+    /// Name of the weapon in weapons.ron
+    pub weapon: String,
+}
+
+/// Pickups that can be left by the entity when it dies. They are spawned by the spawn system.
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct Loot {
+    pub drops: Vec<DropConfig>,
+}
+
+#[derive(SystemDesc)]
+#[system_desc(name(PickupSystemDesc))]
+pub struct PickupSystem {
+    #[system_desc(event_channel_reader)]
+    reader_id: ReaderId<AppEvent>,
+}
+
+impl PickupSystem {
+    pub fn new(reader_id: ReaderId<AppEvent>) -> Self {
+        Self { reader_id }
+    }
+}
+
+impl<'s> System<'s> for PickupSystem {
+    type SystemData = (
+        ReadStorage<'s, WeaponPickup>,
+        WriteStorage<'s, Inventory>,
+        Read<'s, WeaponConfig>,
+        Entities<'s>,
+        Read<'s, EventChannel<AppEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (pickups, mut inventories, weapon_config, entities, events): Self::SystemData,
+    ) {
+        for ev in events.read(&mut self.reader_id) {
+            if let AppEvent::Collect { pickup, collector } = ev {
+                if let (Some(pickup_data), Some(inventory)) =
+                    (pickups.get(*pickup), inventories.get_mut(*collector))
+                {
+                    match weapon_config.weapons.get(&pickup_data.weapon) {
+                        Some(weapon) => {
+                            info!("Pick up weapon {}", pickup_data.weapon);
+                            inventory.add_weapon(&pickup_data.weapon, weapon);
+                        }
+                        None => error!("Weapon {} was not found", pickup_data.weapon),
+                    }
+
+                    // The collision object is removed with the entity.
+                    if let Err(e) = entities.delete(*pickup) {
+                        error!("{}", e);
+                    }
+                }
+            }
+        }
+    }
+}
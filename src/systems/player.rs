@@ -1,22 +1,29 @@
 use crate::{
-    components::Obstacle, config::PlayerConfig, event::AppEvent, systems::AnimationController,
+    config::{FallMode, PlayerConfig},
+    event::AppEvent,
+    systems::{
+        aim::PlayerAim,
+        health::{Damage, DamageType},
+        movement::Velocity,
+        AnimationController,
+    },
 };
 use amethyst::core::{
-    math::{Point3, Vector2, Vector3},
+    math::{Vector2, Vector3},
     shrev::EventChannel,
     timing::Time,
     SystemDesc, Transform,
 };
 use amethyst::derive::SystemDesc;
 use amethyst::ecs::{
-    Component, Entity, Join, Read, ReadStorage, System, SystemData, VecStorage, World, Write,
+    Component, Entities, Entity, Join, Read, System, SystemData, VecStorage, World, Write,
     WriteStorage,
 };
 use amethyst::input::{InputHandler, StringBindings};
 use log::{info, trace};
-use nalgebra::{zero, Isometry2};
-use ncollide2d::query::{Ray, RayCast};
-use std::cmp::Ordering;
+
+/// Movement below that will not play the walk animation (e.g. when pushing against a wall)
+const MIN_ANIMATED_MVT: f32 = 0.01;
 
 fn get_scale(falling_duration: f32, elapsed_time: f32) -> f32 {
     ((falling_duration - elapsed_time) / falling_duration).max(0.0)
@@ -34,6 +41,12 @@ pub enum PlayerStatus {
     /// The player is above ground ! and can move, shoot normally
     Walking,
 
+    /// The player is doing a dodge roll. Bullets cannot hit it and it does not fall.
+    Dashing {
+        direction: Vector2<f32>,
+        elapsed_time: f32,
+    },
+
     /// The player is falling from the arena. The game is over but this state is needed
     /// for transition (animation...)
     Falling {
@@ -57,6 +70,26 @@ impl Default for PlayerStatus {
 #[storage(VecStorage)]
 pub struct Player {
     pub state: PlayerStatus,
+
+    /// Time before the player can dash again.
+    pub dash_cooldown: f32,
+
+    /// How long the player has been out of the walkable areas.
+    pub off_ground_time: f32,
+
+    /// Last position where the player was on a walkable area. The player comes back here
+    /// after falling.
+    pub last_safe_position: Option<Vector2<f32>>,
+}
+
+impl Player {
+    /// Bullets go through the player when it cannot be hit.
+    pub fn is_invincible(&self) -> bool {
+        match self.state {
+            PlayerStatus::Dashing { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(SystemDesc)]
@@ -67,11 +100,13 @@ impl<'s> System<'s> for PlayerSystem {
         WriteStorage<'s, Transform>,
         WriteStorage<'s, Player>,
         WriteStorage<'s, AnimationController>,
-        ReadStorage<'s, Obstacle>,
+        WriteStorage<'s, Velocity>,
         Read<'s, InputHandler<StringBindings>>,
         Read<'s, Time>,
         Write<'s, EventChannel<AppEvent>>,
         Read<'s, PlayerConfig>,
+        Read<'s, PlayerAim>,
+        Entities<'s>,
     );
 
     fn run(
@@ -80,29 +115,58 @@ impl<'s> System<'s> for PlayerSystem {
             mut transforms,
             mut players,
             mut animations,
-            obstacles,
+            mut velocities,
             input,
             time,
             mut event,
             player_config,
+            aim,
+            entities,
         ): Self::SystemData,
     ) {
-        for (player, transform, animation) in
-            (&mut players, &mut transforms, &mut animations).join()
+        for (player, transform, animation, velocity, entity) in (
+            &mut players,
+            &mut transforms,
+            &mut animations,
+            &mut velocities,
+            &entities,
+        )
+            .join()
         {
+            player.dash_cooldown = (player.dash_cooldown - time.delta_seconds()).max(0.0);
+
             // idle state.
             //
             match player.state {
-                PlayerStatus::Walking => self.player_walk(
-                    transform,
+                PlayerStatus::Walking => {
+                    if player.dash_cooldown <= 0.0
+                        && input.action_is_down("dash").unwrap_or(false)
+                    {
+                        // Dash where the player is going, or where it is aiming when it does
+                        // not move.
+                        let direction = movement_input(&input)
+                            .try_normalize(std::f32::EPSILON)
+                            .unwrap_or(aim.direction);
+                        info!("Dash towards {:?}", direction);
+                        player.dash_cooldown = player_config.dash.cooldown;
+                        player.state = PlayerStatus::Dashing {
+                            direction,
+                            elapsed_time: 0.0,
+                        };
+                    } else {
+                        self.player_walk(velocity, animation, &input, &player_config);
+                    }
+                }
+                PlayerStatus::Dashing { .. } => self.player_dash(
+                    player,
+                    velocity,
                     animation,
-                    &input,
-                    &obstacles,
                     time.delta_seconds(),
                     &player_config,
                 ),
                 PlayerStatus::Falling { .. } => self.player_fall(
                     player,
+                    entity,
                     transform,
                     animation,
                     time.delta_seconds(),
@@ -119,6 +183,7 @@ impl PlayerSystem {
     fn player_fall(
         &self,
         player: &mut Player,
+        entity: Entity,
         transform: &mut Transform,
         animation: &mut AnimationController,
         time_delta: f32,
@@ -146,87 +211,108 @@ impl PlayerSystem {
         }
 
         if has_fallen {
-            info!("Player has fallen, game over");
-            player.state = PlayerStatus::GameOver;
-            event.single_write(AppEvent::GameOver);
+            match (player_config.fall.mode, player.last_safe_position) {
+                (FallMode::Respawn { damage }, Some(position)) => {
+                    info!("Player has fallen, respawn at {:?}", position);
+                    transform.set_scale(Vector3::new(1.0, 1.0, 1.0));
+                    transform.set_rotation_euler(0.0, 0.0, 0.0);
+                    transform.set_translation_x(position.x);
+                    transform.set_translation_y(position.y);
+                    player.state = PlayerStatus::Walking;
+                    player.off_ground_time = 0.0;
+                    event.single_write(AppEvent::EntityHit {
+                        target: entity,
+                        attacker: None,
+                        damage: Damage::new(damage, DamageType::Physical),
+                    });
+                }
+                _ => {
+                    info!("Player has fallen, game over");
+                    player.state = PlayerStatus::GameOver;
+                    event.single_write(AppEvent::GameOver);
+                }
+            }
         }
     }
 
-    /// Player walks with the input axis. Raycast to not walk in walls
+    /// Player walks with the input axis. It will slide along the walls instead of going
+    /// through them. The movement itself is applied by the movement system.
     fn player_walk(
         &self,
-        transform: &mut Transform,
+        velocity: &mut Velocity,
         animation: &mut AnimationController,
         input: &InputHandler<StringBindings>,
-        obstacles: &ReadStorage<Obstacle>,
-        elapsed_time: f32,
         player_config: &Read<PlayerConfig>,
     ) {
         animation.current_animation = None;
-        let movement_x = input.axis_value("x").unwrap_or(0.0);
-        let movement_y = input.axis_value("y").unwrap_or(0.0);
-
-        // Normalize the vector so that the player does not move faster
-        // diagonally...
-        let mvt = Vector2::new(movement_x, movement_y).normalize()
-            * player_config.player_speed
-            * elapsed_time;
-        if mvt.x > 0.0 && self.can_move_right(transform, &obstacles) {
-            transform.prepend_translation_x(mvt.x);
-            animation.current_animation = Some("walk_right".to_string());
-        } else if mvt.x < 0.0 && self.can_move_left(transform, &obstacles) {
-            transform.prepend_translation_x(mvt.x);
-            animation.current_animation = Some("walk_left".to_string());
+        let mut direction = movement_input(input);
+        if direction.norm_squared() == 0.0 {
+            return;
         }
 
-        if mvt.y > 0.0 && self.can_move_up(transform, &obstacles) {
-            transform.prepend_translation_y(mvt.y);
-            animation.current_animation = Some("walk_up".to_string());
-        } else if mvt.y < 0.0 && self.can_move_down(transform, &obstacles) {
-            transform.prepend_translation_y(mvt.y);
-            animation.current_animation = Some("walk_down".to_string());
+        // Normalize the vector so that the player does not move faster
+        // diagonally... The stick can still be used to walk slowly.
+        if direction.norm() > 1.0 {
+            direction = direction.normalize();
         }
-    }
+        velocity.desired = direction * player_config.player_speed;
 
-    fn can_move_up(&self, t: &mut Transform, obstacles: &ReadStorage<Obstacle>) -> bool {
-        let ray_direction = Vector2::<f32>::y();
-        self.can_move_direction(t, ray_direction, obstacles)
-    }
-    fn can_move_down(&self, t: &mut Transform, obstacles: &ReadStorage<Obstacle>) -> bool {
-        let ray_direction = -Vector2::<f32>::y();
-        self.can_move_direction(t, ray_direction, obstacles)
-    }
-    fn can_move_left(&self, t: &mut Transform, obstacles: &ReadStorage<Obstacle>) -> bool {
-        let ray_direction = -Vector2::<f32>::x();
-        self.can_move_direction(t, ray_direction, obstacles)
-    }
-    fn can_move_right(&self, t: &mut Transform, obstacles: &ReadStorage<Obstacle>) -> bool {
-        let ray_direction = Vector2::<f32>::x();
-        self.can_move_direction(t, ray_direction, obstacles)
+        // Animation follows what the player actually did, not what was asked. The movement
+        // system has not run yet this frame so it is the movement of the last frame.
+        set_move_animation(animation, "walk", velocity.walked);
     }
 
-    fn can_move_direction(
+    /// Player moves quickly in the dash direction. It goes back to walking at the end of the
+    /// dash.
+    fn player_dash(
         &self,
-        t: &mut Transform,
-        direction: Vector2<f32>,
-        obstacles: &ReadStorage<Obstacle>,
-    ) -> bool {
-        let player_origin = t
-            .isometry()
-            .transform_point(&Point3::new(0.0, 0.0, 0.0))
-            .xy();
-        let ray = Ray::new(player_origin, direction);
-        let identity = Isometry2::new(zero(), zero());
-
-        let cast = obstacles
-            .join()
-            .filter_map(|obs| obs.aabb.toi_with_ray(&identity, &ray, false))
-            .min_by(|a: &f32, b: &f32| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        player: &mut Player,
+        velocity: &mut Velocity,
+        animation: &mut AnimationController,
+        time_delta: f32,
+        player_config: &Read<PlayerConfig>,
+    ) {
+        let config = player_config.dash;
+        let mut dash_over = false;
+        if let PlayerStatus::Dashing {
+            direction,
+            ref mut elapsed_time,
+        } = player.state
+        {
+            *elapsed_time += time_delta;
+            let speed = config.distance / config.duration;
+            velocity.desired = direction * speed;
+
+            animation.current_animation = None;
+            set_move_animation(animation, "dash", direction);
+            dash_over = *elapsed_time >= config.duration;
+        }
 
-        if let Some(toi) = cast {
-            toi > 4.0
-        } else {
-            true
+        if dash_over {
+            player.state = PlayerStatus::Walking;
         }
     }
 }
+
+/// Movement asked by the keyboard and the left stick of the gamepad.
+fn movement_input(input: &InputHandler<StringBindings>) -> Vector2<f32> {
+    Vector2::new(
+        input.axis_value("x").unwrap_or(0.0) + input.axis_value("move_x").unwrap_or(0.0),
+        input.axis_value("y").unwrap_or(0.0) + input.axis_value("move_y").unwrap_or(0.0),
+    )
+}
+
+/// Play the animation (`walk`, `dash`...) that matches the direction of the movement.
+fn set_move_animation(animation: &mut AnimationController, name: &str, mvt: Vector2<f32>) {
+    if mvt.x > MIN_ANIMATED_MVT {
+        animation.current_animation = Some(format!("{}_right", name));
+    } else if mvt.x < -MIN_ANIMATED_MVT {
+        animation.current_animation = Some(format!("{}_left", name));
+    }
+
+    if mvt.y > MIN_ANIMATED_MVT {
+        animation.current_animation = Some(format!("{}_up", name));
+    } else if mvt.y < -MIN_ANIMATED_MVT {
+        animation.current_animation = Some(format!("{}_down", name));
+    }
+}
@@ -1,11 +1,13 @@
 //! Will manage enemy spawn :)
 //! This is done on event. Should spawn an enemy at one of the spawn locations.
+//! The enemies are chosen among the wave enemies of the enemy configuration.
 //!
-//!
+//! Also spawns what the enemies drop when they die.
 use crate::{
+    config::EnemyConfig,
     event::AppEvent,
     objects::enemy::EnemySpawner,
-    systems::{enemy::EnemyType, MyCollisionWorld},
+    systems::{pickup::Loot, MyCollisionWorld},
     tilemap::Tilemap,
     z_layers::PROPS_LAYER,
 };
@@ -16,7 +18,10 @@ use amethyst::{
         SystemDesc, Transform,
     },
     derive::SystemDesc,
-    ecs::{Component, Entities, LazyUpdate, Read, System, SystemData, VecStorage, World, Write},
+    ecs::{
+        Component, Entities, LazyUpdate, Read, ReadStorage, System, SystemData, VecStorage, World,
+        Write,
+    },
 };
 use log::{error, info};
 use rand::seq::SliceRandom;
@@ -50,12 +55,29 @@ impl<'s> System<'s> for SpawnSystem {
         Entities<'s>,
         Read<'s, LazyUpdate>,
         Write<'s, MyCollisionWorld>,
+        Read<'s, EnemyConfig>,
+        // For the drops of dead enemies.
+        ReadStorage<'s, Loot>,
+        ReadStorage<'s, Transform>,
     );
 
     fn run(
         &mut self,
-        (tilemap, events, spawner, entities, updater, mut collision_world): Self::SystemData,
+        (
+            tilemap,
+            events,
+            spawner,
+            entities,
+            updater,
+            mut collision_world,
+            enemy_config,
+            loots,
+            transforms,
+        ): Self::SystemData,
     ) {
+        // Entities whose loot has been dropped this frame. The Loot component is only
+        // removed at the end of the frame.
+        let mut dropped = vec![];
         // only one waves component.
         for ev in events.read(&mut self.reader_id) {
             match ev {
@@ -64,7 +86,8 @@ impl<'s> System<'s> for SpawnSystem {
                     let mut rng = rand::thread_rng();
                     for _ in 0..*x {
                         let location = tilemap.spawn_locations.choose(&mut rng);
-                        if let Some(location) = location {
+                        let name = enemy_config.wave_enemies.choose(&mut rng);
+                        if let (Some(location), Some(name)) = (location, name) {
                             println!("Will spawn at loc {:?}", location);
                             let mut t = Transform::default();
                             t.append_translation_xyz(location.x, location.y, PROPS_LAYER);
@@ -72,12 +95,12 @@ impl<'s> System<'s> for SpawnSystem {
                                 &entities,
                                 &updater,
                                 &mut collision_world,
-                                EnemyType::Simple,
+                                name,
                                 t,
                             ) {
                                 error!(
                                     "Could not find enemy {:?} in Spawner - Check init...",
-                                    EnemyType::Simple
+                                    name
                                 );
                             }
                         } else {
@@ -93,12 +116,30 @@ impl<'s> System<'s> for SpawnSystem {
                         &entities,
                         &updater,
                         &mut collision_world,
-                        EnemyType::CreepyFirstBoss,
+                        &enemy_config.boss,
                         t,
                     ) {
                         error!("Could not spawn my creepy boss");
                     }
                 }
+                AppEvent::EnemyDied(e) => {
+                    // The same death can be received several times, so the loot is removed
+                    // once it has been dropped.
+                    if dropped.contains(e) {
+                        continue;
+                    }
+                    if let (Some(loot), Some(t)) = (loots.get(*e), transforms.get(*e)) {
+                        spawner.spawn_drops(
+                            &entities,
+                            &updater,
+                            &mut collision_world,
+                            &loot.drops,
+                            t.translation().xy(),
+                        );
+                        updater.remove::<Loot>(*e);
+                        dropped.push(*e);
+                    }
+                }
                 _ => (),
             }
         }
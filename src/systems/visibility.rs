@@ -0,0 +1,86 @@
+//! Compute how visible the player is. The enemies will see the player from further away
+//! when the player is in the light, moving fast or making noise.
+use crate::{components::Light, config::PlayerConfig, systems::Player};
+use amethyst::{
+    core::{math::Vector2, timing::Time, SystemDesc, Transform},
+    derive::SystemDesc,
+    ecs::{Join, Read, ReadStorage, System, SystemData, World, Write},
+};
+
+/// Visibility of the player for the current frame. All the scores are between 0 and 1.
+#[derive(Debug, Default)]
+pub struct PlayerVisibility {
+    /// Final visibility score used by the enemies.
+    pub score: f32,
+
+    /// How much the player is exposed to the lights.
+    pub light: f32,
+
+    /// How fast the player is moving compared to the maximum speed.
+    pub movement: f32,
+
+    /// Noise made by the player's actions (shooting...). Fades over time.
+    pub noise: f32,
+
+    /// Used to compute the player speed.
+    last_position: Option<Vector2<f32>>,
+}
+
+impl PlayerVisibility {
+    /// Add some noise, e.g. when the player is shooting.
+    pub fn add_noise(&mut self, noise: f32) {
+        self.noise = (self.noise + noise).min(1.0);
+    }
+}
+
+#[derive(SystemDesc)]
+pub struct VisibilitySystem;
+
+impl<'s> System<'s> for VisibilitySystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Light>,
+        Read<'s, Time>,
+        Read<'s, PlayerConfig>,
+        Write<'s, PlayerVisibility>,
+    );
+
+    fn run(
+        &mut self,
+        (players, transforms, lights, time, player_config, mut visibility): Self::SystemData,
+    ) {
+        let config = player_config.visibility;
+        let delta_time = time.delta_seconds();
+
+        if let Some((_, transform)) = (&players, &transforms).join().next() {
+            let position = transform.translation().xy();
+
+            visibility.light = (&lights, &transforms)
+                .join()
+                .map(|(light, t)| {
+                    let distance = (t.translation().xy() - position).norm();
+                    light.intensity * (1.0 - distance / light.radius)
+                })
+                .fold(0.0f32, f32::max)
+                .min(1.0);
+
+            visibility.movement = match visibility.last_position {
+                Some(last_position) if delta_time > 0.0 => {
+                    let speed = (position - last_position).norm() / delta_time;
+                    (speed / player_config.player_speed).min(1.0)
+                }
+                _ => 0.0,
+            };
+            visibility.last_position = Some(position);
+        }
+
+        visibility.noise = (visibility.noise - config.noise_decay * delta_time).max(0.0);
+        visibility.score = (config.ambient
+            + config.light_weight * visibility.light
+            + config.movement_weight * visibility.movement
+            + visibility.noise)
+            .max(0.0)
+            .min(1.0);
+    }
+}
@@ -134,6 +134,7 @@ impl<'s> System<'s> for WaveSystem {
         // poll the events even if there is no wave configured.. can we lose some
         // events? dunno...
         let mut to_spawn = 0;
+        let mut reinforcements = 0;
 
         for ev in events.read(&mut self.reader_id) {
             match ev {
@@ -141,6 +142,10 @@ impl<'s> System<'s> for WaveSystem {
                     debug!("Enemy died :D");
                     to_spawn += 1;
                 }
+                AppEvent::Reinforcements(nb) => {
+                    debug!("Reinforcements are coming");
+                    reinforcements += nb;
+                }
                 AppEvent::NextWave | AppEvent::SpawnBoss => {
                     // Next wave ! If no more wave. then just stop :D
                     if let Some(ref mut waves) = (&mut waves).join().next() {
@@ -160,6 +165,22 @@ impl<'s> System<'s> for WaveSystem {
             }
         }
 
+        if reinforcements > 0 {
+            // Reinforcements are part of the current wave so they have to be killed before
+            // going to the next one. They still come when there is no wave to track them (no
+            // waves in this map, boss fight...).
+            let current_wave = (&mut waves).join().next().and_then(|w| match w.status {
+                WaveControllerStatus::Running => w.waves.get_mut(w.current_wave),
+                WaveControllerStatus::Finished => None,
+            });
+            if let Some(wave) = current_wave {
+                wave.current_enemies += reinforcements;
+            } else {
+                debug!("Reinforcements are not part of a wave");
+            }
+            events.single_write(AppEvent::SpawnEnemy(reinforcements));
+        }
+
         // only one waves component.
         if let Some(ref mut waves) = (&mut waves).join().next() {
             if let WaveControllerStatus::Finished = waves.status {
@@ -1,11 +1,13 @@
 //! Module to create entities from a Tiled map.
 //!
 //!
-use crate::components::Obstacle;
+use crate::components::{Light, Obstacle};
 use crate::states::ARENA_HEIGHT;
 use crate::{
+    event::EventConfig,
     systems::{
-        Animation, AnimationController, Collider, ColliderObjectType, MyCollisionWorld, Walkable,
+        alarm::AlarmProp, door::Door, pickup::WeaponPickup, Animation, AnimationController,
+        Collider, ColliderData, ColliderObjectType, MyCollisionWorld, Trigger, Walkable,
     },
     util::load_spritesheet,
     z_layers::*,
@@ -14,7 +16,7 @@ use amethyst::utils::application_root_dir;
 use amethyst::{
     assets::Handle,
     core::{
-        math::{geometry::Point2, Vector2},
+        math::{geometry::Point2, Rotation2, Vector2},
         transform::Transform,
     },
     ecs::Entity,
@@ -22,7 +24,10 @@ use amethyst::{
     renderer::{debug_drawing::DebugLinesComponent, palette::Srgba, SpriteRender, SpriteSheet},
 };
 use log::warn;
-use ncollide2d::bounding_volume::AABB;
+use ncollide2d::{
+    bounding_volume::{self, AABB},
+    pipeline::world::CollisionWorld,
+};
 use std::path::PathBuf;
 use tiled::parse_file;
 
@@ -40,6 +45,9 @@ pub struct Tilemap {
 
     /// Place where the boss will spawn
     pub boss_spawn: Option<Vector2<f32>>,
+
+    /// Area covered by the map.
+    pub bounds: Option<AABB<f32>>,
 }
 
 impl Tilemap {
@@ -47,6 +55,21 @@ impl Tilemap {
         &self.all_entities
     }
 
+    /// Return true if the position is on the map, or at most `margin` away from it.
+    pub fn is_inside(&self, position: Vector2<f32>, margin: f32) -> bool {
+        match self.bounds {
+            Some(ref bounds) => {
+                let mins = bounds.mins();
+                let maxs = bounds.maxs();
+                position.x >= mins.x - margin
+                    && position.x <= maxs.x + margin
+                    && position.y >= mins.y - margin
+                    && position.y <= maxs.y + margin
+            }
+            None => true,
+        }
+    }
+
     /// Load the map from the tmx file.
     pub fn load(map_name: &str, world: &mut World) -> Tilemap {
         let mut tilemap = Tilemap::default();
@@ -70,6 +93,11 @@ impl Tilemap {
 
         first_gids.sort();
 
+        tilemap.bounds = Some(AABB::new(
+            Point2::new(0.0, ARENA_HEIGHT - map.height as f32 * 16.0),
+            Point2::new(map.width as f32 * 16.0, ARENA_HEIGHT),
+        ));
+
         for layer in &map.layers {
             let layer_name = layer.name.to_lowercase();
             for (y, row) in layer.tiles.iter().enumerate() {
@@ -105,6 +133,7 @@ impl Tilemap {
 
         tilemap.load_colliders(&map, world);
         tilemap.load_walkable(&map, world);
+        tilemap.load_triggers(&map, world);
         tilemap.load_props(&map, world, &first_gids, &tileset_names);
         tilemap.load_player_spawn(&map);
         //tilemap.load_enemies(&map, world);
@@ -199,7 +228,7 @@ impl Tilemap {
     //                            &entities,
     //                            &updater,
     //                            &mut collision,
-    //                            EnemyType::Simple,
+    //                            "simple",
     //                            transform,
     //                        ) {
     //                            self.all_entities.push(entity);
@@ -210,8 +239,8 @@ impl Tilemap {
     //        }
     //    }
     //
-    /// Load the colliders from the map. They are attached as objects in an
-    /// object layer called `colliders`
+    /// Load the walkable areas from the map. They are attached as objects in an
+    /// object layer called `walkable`. Polylines are closed to form an area.
     fn load_walkable(&mut self, map: &tiled::Map, world: &mut World) {
         if let Some(ref group) = map
             .object_groups
@@ -220,29 +249,23 @@ impl Tilemap {
             .nth(0)
         {
             for obj in &group.objects {
-                // Just do rectangles for now.
-                if let tiled::ObjectShape::Rect { width, height, .. } = obj.shape {
-                    let (x, y) = convert_tiled_xy(obj.x, obj.y);
-                    let max = Point2::new(x + width, y);
-                    let min = Point2::new(x, y - height);
-                    let position = Vector2::new(x + width / 2.0, y - height / 2.0);
-
-                    let collider = {
-                        let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
-                        Collider::new_rect(
-                            position,
-                            width,
-                            height,
-                            &mut collworld.world,
-                            ColliderObjectType::None,
-                            None,
-                            None,
-                        )
-                    };
+                let collider = {
+                    let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
+                    create_object_collider(
+                        obj,
+                        &mut collworld.world,
+                        ColliderObjectType::Walkable,
+                        None,
+                        true,
+                    )
+                };
+
+                if let Some(collider) = collider {
+                    let aabb = collider_aabb(&collider, world);
                     let mut debug_line = DebugLinesComponent::with_capacity(10);
                     debug_line.add_rectangle_2d(
-                        min,
-                        max,
+                        *aabb.mins(),
+                        *aabb.maxs(),
                         DEBUG_LAYER,
                         Srgba::new(0.0, 0.0, 1.0, 0.5),
                     );
@@ -272,31 +295,23 @@ impl Tilemap {
             .nth(0)
         {
             for obj in &group.objects {
-                // Just do rectangles for now.
-                if let tiled::ObjectShape::Rect { width, height, .. } = obj.shape {
-                    let (x, y) = convert_tiled_xy(obj.x, obj.y);
-                    let max = Point2::new(x + width, y);
-                    let min = Point2::new(x, y - height);
-                    let aabb = AABB::new(min, max);
-
-                    let position = Vector2::new(x + width / 2.0, y - height / 2.0);
-                    let collider = {
-                        let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
-                        Collider::new_rect(
-                            position,
-                            width,
-                            height,
-                            &mut collworld.world,
-                            ColliderObjectType::Wall,
-                            None,
-                            None,
-                        )
-                    };
-
+                let collider = {
+                    let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
+                    create_object_collider(
+                        obj,
+                        &mut collworld.world,
+                        ColliderObjectType::Wall,
+                        None,
+                        false,
+                    )
+                };
+
+                if let Some(collider) = collider {
+                    let aabb = collider_aabb(&collider, world);
                     let mut debug_line = DebugLinesComponent::with_capacity(10);
                     debug_line.add_rectangle_2d(
-                        min,
-                        max,
+                        *aabb.mins(),
+                        *aabb.maxs(),
                         DEBUG_LAYER,
                         Srgba::new(1.0, 0.0, 0.0, 1.0),
                     );
@@ -316,6 +331,49 @@ impl Tilemap {
         }
     }
 
+    /// Load the trigger volumes from the map. They are attached as objects in an object
+    /// layer called `triggers`. The events to send are in the `on_enter` and `on_exit`
+    /// properties, written in RON (e.g. `Dialog(["Hello"])` or `OpenDoor("exit")`). If the
+    /// `once` property is true, the trigger is removed after the player entered it.
+    fn load_triggers(&mut self, map: &tiled::Map, world: &mut World) {
+        if let Some(ref group) = map
+            .object_groups
+            .iter()
+            .filter(|&g| g.name == String::from("triggers"))
+            .nth(0)
+        {
+            for obj in &group.objects {
+                let collider = {
+                    let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
+                    create_object_collider(
+                        obj,
+                        &mut collworld.world,
+                        ColliderObjectType::Trigger,
+                        Some(&[ColliderObjectType::Player]),
+                        true,
+                    )
+                };
+
+                if let Some(collider) = collider {
+                    let trigger = Trigger {
+                        on_enter: event_property(obj, "on_enter"),
+                        on_exit: event_property(obj, "on_exit"),
+                        once: match obj.properties.get("once") {
+                            Some(tiled::PropertyValue::BoolValue(once)) => *once,
+                            _ => false,
+                        },
+                    };
+
+                    let entity = world.create_entity().with(trigger).with(collider).build();
+
+                    let collision_world = world.get_mut::<MyCollisionWorld>().unwrap();
+                    collider.set_entity(&mut collision_world.world, entity);
+                    self.all_entities.push(entity);
+                }
+            }
+        }
+    }
+
     /// Will load object from the tilemap.
     fn load_props(
         &mut self,
@@ -340,6 +398,34 @@ impl Tilemap {
                 let mut transform = Transform::default();
                 transform.set_translation_xyz(x + 8.0, y + 8.0, PROPS_LAYER);
 
+                // Doors are blocking the way and pickups are collected by the player so they
+                // need a collider. It has to be created before the entity builder borrows the
+                // world.
+                let obj_type = obj.obj_type.to_lowercase();
+                let collider_type = match obj_type.as_str() {
+                    "door" => Some((ColliderObjectType::Wall, None)),
+                    "weapon" => Some((
+                        ColliderObjectType::Pickup,
+                        Some(&[ColliderObjectType::Player][..]),
+                    )),
+                    _ => None,
+                };
+                let prop_collider = collider_type.map(|(collider_type, collide_with)| {
+                    let collider = {
+                        let collworld = world.get_mut::<MyCollisionWorld>().unwrap();
+                        Collider::new_rect(
+                            Vector2::new(x + 8.0, y + 8.0),
+                            16.0,
+                            16.0,
+                            &mut collworld.world,
+                            collider_type,
+                            collide_with,
+                            None,
+                        )
+                    };
+                    (collider, collider_aabb(&collider, world))
+                });
+
                 let mut entity_builder = world
                     .create_entity()
                     .with(SpriteRender {
@@ -348,8 +434,19 @@ impl Tilemap {
                     })
                     .with(transform);
 
-                match obj.obj_type.to_lowercase().as_str() {
+                match obj_type.as_str() {
                     "light" => {
+                        let radius = match obj.properties.get("radius") {
+                            Some(tiled::PropertyValue::FloatValue(radius)) => *radius,
+                            Some(tiled::PropertyValue::IntValue(radius)) => *radius as f32,
+                            _ => DEFAULT_LIGHT_RADIUS,
+                        };
+                        let intensity = match obj.properties.get("intensity") {
+                            Some(tiled::PropertyValue::FloatValue(intensity)) => *intensity,
+                            _ => 1.0,
+                        };
+                        entity_builder = entity_builder.with(Light { radius, intensity });
+
                         if let Some(tiled::PropertyValue::StringValue(ref anim)) =
                             obj.properties.get(&"animation".to_string())
                         {
@@ -367,16 +464,276 @@ impl Tilemap {
                             entity_builder = entity_builder.with(animation_controller);
                         }
                     }
+                    "door" => {
+                        let open_sprite = match obj.properties.get("open_sprite") {
+                            Some(tiled::PropertyValue::IntValue(sprite)) => Some(*sprite as usize),
+                            _ => None,
+                        };
+                        if let Some((collider, aabb)) = &prop_collider {
+                            entity_builder = entity_builder
+                                .with(Door {
+                                    name: obj.name.clone(),
+                                    open_sprite,
+                                })
+                                .with(Obstacle { aabb: aabb.clone() })
+                                .with(*collider);
+                        }
+                    }
+                    "alarm" => {
+                        let radius = match obj.properties.get("radius") {
+                            Some(tiled::PropertyValue::FloatValue(radius)) => *radius,
+                            Some(tiled::PropertyValue::IntValue(radius)) => *radius as f32,
+                            _ => DEFAULT_ALARM_RADIUS,
+                        };
+                        let ringing_sprite = match obj.properties.get("ringing_sprite") {
+                            Some(tiled::PropertyValue::IntValue(sprite)) => Some(*sprite as usize),
+                            _ => None,
+                        };
+                        entity_builder = entity_builder.with(AlarmProp {
+                            radius,
+                            on_ring: event_property(obj, "on_ring"),
+                            idle_sprite: real_tile_id as usize,
+                            ringing_sprite,
+                            ringing: false,
+                        });
+                    }
+                    "weapon" => {
+                        if let Some(tiled::PropertyValue::StringValue(ref weapon)) =
+                            obj.properties.get("weapon")
+                        {
+                            entity_builder = entity_builder.with(WeaponPickup {
+                                weapon: weapon.clone(),
+                            });
+                        } else {
+                            warn!("Weapon pickup {} has no weapon property", obj.id);
+                        }
+                        if let Some((collider, _)) = &prop_collider {
+                            entity_builder = entity_builder.with(*collider);
+                        }
+                    }
                     _ => (),
                 }
 
                 let entity = entity_builder.build();
+                if let Some((collider, _)) = prop_collider {
+                    let collision_world = world.get_mut::<MyCollisionWorld>().unwrap();
+                    collider.set_entity(&mut collision_world.world, entity);
+                }
                 self.all_entities.push(entity);
             }
         }
     }
 }
 
+/// Radius of the lights when it is not set in the map.
+const DEFAULT_LIGHT_RADIUS: f32 = 64.0;
+
+/// Radius of the alarm props when it is not set in the map.
+const DEFAULT_ALARM_RADIUS: f32 = 150.0;
+
+/// Number of points used to approximate an ellipse that is not a circle.
+const ELLIPSE_POINTS: usize = 16;
+
+/// Create the collider that matches the shape of a Tiled object.
+/// - Rectangles are... rectangles
+/// - Ellipses are circles if width and height are the same, otherwise they are approximated
+/// with a convex polygon.
+/// - Polygons are convex polygons. Concave polygons are not supported, split them in several
+/// objects in Tiled.
+/// - Polylines are segments, unless `close_polylines` is true. In that case, they are treated as
+/// polygons.
+///
+/// The rotation of the object is applied around its position, like Tiled does.
+///
+/// Returns None (and logs) when the object cannot be turned into a collider.
+fn create_object_collider(
+    obj: &tiled::Object,
+    collision_world: &mut CollisionWorld<f32, ColliderData>,
+    collider_type: ColliderObjectType,
+    collide_with: Option<&[ColliderObjectType]>,
+    close_polylines: bool,
+) -> Option<Collider> {
+    // Top left corner for rectangles and ellipses, first point for the polygons.
+    let (x, y) = convert_tiled_xy(obj.x, obj.y);
+    let position = Vector2::new(x, y);
+    // Tiled rotates clockwise with the y axis going down.
+    let rotation = Rotation2::new(-obj.rotation.to_radians());
+    let rotated = obj.rotation.abs() > std::f32::EPSILON;
+
+    let collider = match obj.shape {
+        tiled::ObjectShape::Rect { width, height } if !rotated => Some(Collider::new_rect(
+            position + Vector2::new(width / 2.0, -height / 2.0),
+            width,
+            height,
+            collision_world,
+            collider_type,
+            collide_with,
+            None,
+        )),
+        tiled::ObjectShape::Rect { width, height } => {
+            let corners = [
+                Point2::new(0.0, 0.0),
+                Point2::new(width, 0.0),
+                Point2::new(width, -height),
+                Point2::new(0.0, -height),
+            ];
+            Collider::new_polygon(
+                position,
+                &corners.iter().map(|p| rotation * p).collect::<Vec<_>>(),
+                collision_world,
+                collider_type,
+                collide_with,
+                None,
+            )
+        }
+        tiled::ObjectShape::Ellipse { width, height } => {
+            let center = position + rotation * Vector2::new(width / 2.0, -height / 2.0);
+            if (width - height).abs() < std::f32::EPSILON {
+                Some(Collider::new_circle(
+                    center,
+                    width / 2.0,
+                    collision_world,
+                    collider_type,
+                    collide_with,
+                    None,
+                ))
+            } else {
+                let points = (0..ELLIPSE_POINTS)
+                    .map(|i| {
+                        let angle = 2.0 * std::f32::consts::PI * i as f32 / ELLIPSE_POINTS as f32;
+                        let point =
+                            Point2::new(width / 2.0 * angle.cos(), height / 2.0 * angle.sin());
+                        rotation * point
+                    })
+                    .collect::<Vec<_>>();
+                Collider::new_polygon(
+                    center,
+                    &points,
+                    collision_world,
+                    collider_type,
+                    collide_with,
+                    None,
+                )
+            }
+        }
+        tiled::ObjectShape::Polygon { ref points } => create_polygon_collider(
+            obj,
+            position,
+            &convert_tiled_points(points, &rotation),
+            collision_world,
+            collider_type,
+            collide_with,
+        ),
+        tiled::ObjectShape::Polyline { ref points } => {
+            if close_polylines {
+                create_polygon_collider(
+                    obj,
+                    position,
+                    &convert_tiled_points(points, &rotation),
+                    collision_world,
+                    collider_type,
+                    collide_with,
+                )
+            } else if points.len() >= 2 {
+                Some(Collider::new_polyline(
+                    position,
+                    convert_tiled_points(points, &rotation),
+                    collision_world,
+                    collider_type,
+                    collide_with,
+                    None,
+                ))
+            } else {
+                None
+            }
+        }
+    };
+
+    if collider.is_none() {
+        warn!("Cannot create collider for object {} ({:?})", obj.id, obj.shape);
+    }
+    collider
+}
+
+/// Polygon collider from the points of a Tiled polygon. Concave polygons are skipped, the
+/// convex hull would block places that the map shows as open.
+fn create_polygon_collider(
+    obj: &tiled::Object,
+    position: Vector2<f32>,
+    points: &[Point2<f32>],
+    collision_world: &mut CollisionWorld<f32, ColliderData>,
+    collider_type: ColliderObjectType,
+    collide_with: Option<&[ColliderObjectType]>,
+) -> Option<Collider> {
+    if !is_convex(points) {
+        warn!("Object {} is a concave polygon, split it in convex parts in Tiled", obj.id);
+        return None;
+    }
+    Collider::new_polygon(
+        position,
+        points,
+        collision_world,
+        collider_type,
+        collide_with,
+        None,
+    )
+}
+
+/// A polygon is convex when all its corners turn in the same direction. Aligned points are
+/// fine.
+fn is_convex(points: &[Point2<f32>]) -> bool {
+    let n = points.len();
+    let mut direction = 0.0;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        let c = points[(i + 2) % n];
+        let turn = (b - a).perp(&(c - b));
+        if turn.abs() <= std::f32::EPSILON {
+            continue;
+        }
+        if turn * direction < 0.0 {
+            return false;
+        }
+        direction = turn;
+    }
+    true
+}
+
+/// Read an event written in RON from the object properties.
+fn event_property(obj: &tiled::Object, name: &str) -> Option<EventConfig> {
+    if let Some(tiled::PropertyValue::StringValue(ref value)) = obj.properties.get(name) {
+        match ron::de::from_str(value) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                warn!("Cannot read {} of object {}: {}", name, obj.id, e);
+                None
+            }
+        }
+    } else {
+        None
+    }
+}
+
+/// Axis aligned bounding box of the collider, in world coordinates.
+fn collider_aabb(collider: &Collider, world: &World) -> AABB<f32> {
+    let collision_world = world.read_resource::<MyCollisionWorld>();
+    let obj = collision_world
+        .world
+        .collision_object(collider.handle)
+        .expect("Collider was just created");
+    bounding_volume::aabb(obj.shape().as_ref(), obj.position())
+}
+
+/// Points of Tiled polygons are relative to the object position, with the y axis going down.
+/// They are rotated with the object.
+fn convert_tiled_points(points: &[(f32, f32)], rotation: &Rotation2<f32>) -> Vec<Point2<f32>> {
+    points
+        .iter()
+        .map(|&(x, y)| rotation * Point2::new(x, -y))
+        .collect()
+}
+
 fn convert_tiled_xy(x: f32, y: f32) -> (f32, f32) {
     (x - 1.0, ARENA_HEIGHT - (y - 1.0))
 }
@@ -4,15 +4,10 @@ use amethyst::{
     ecs::{
         error::WrongGeneration,
         prelude::{Entity, World, WorldExt},
-        Entities, ReadStorage,
     },
     renderer::{ImageFormat, SpriteSheet, SpriteSheetFormat, Texture},
 };
 
-use crate::systems::{Collider, ColliderData};
-use log::{error, warn};
-use ncollide2d::pipeline::world::CollisionWorld;
-
 use std::iter;
 
 /// delete the specified root entity and all of its descendents as specified
@@ -31,28 +26,6 @@ pub fn delete_hierarchy(root: Entity, world: &mut World) -> Result<(), WrongGene
     world.delete_entities(&entities)
 }
 
-/// Will remove an entity that has a collider. It needs to remove both the collision
-/// object in the collision world and the normal entity.
-pub fn delete_entity_with_collider(
-    entity: Entity,
-    colliders: &ReadStorage<Collider>,
-    entities: &Entities,
-    world: &mut CollisionWorld<f32, ColliderData>,
-) {
-    if let Some(collider) = colliders.get(entity) {
-        if let None = world.collision_object(collider.handle) {
-            warn!("Collision word could not find the handle for the collider. Will not delete otherwise it will panic")
-        } else {
-            world.remove(&[collider.handle]);
-        }
-    }
-    // what can happen is a wrong generation error. make sure to display the error
-    // brightly but the game does not really know how to handle that...
-    if let Err(e) = entities.delete(entity) {
-        error!("{}", e);
-    }
-}
-
 /// Load the texture from the name
 pub fn load_spritesheet(texture_name: &str, world: &mut World) -> Handle<SpriteSheet> {
     let texture_path = format!("texture/{}.png", texture_name);
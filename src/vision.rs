@@ -0,0 +1,320 @@
+//! Line of sight queries. Obstacles block the vision so that the player can hide
+//! behind walls.
+//!
+//! `Vision` can be added to the `SystemData` of any system that needs to know what an
+//! entity can see (enemies, turrets...).
+use crate::{
+    components::Obstacle,
+    systems::{Collider, ColliderObjectType, MyCollisionWorld},
+};
+use amethyst::{
+    core::{
+        math::{Point2, Vector2},
+        Transform,
+    },
+    ecs::{Entities, Entity, Join, Read, ReadStorage, ResourceId, SystemData, World},
+};
+use ncollide2d::{
+    math::Isometry,
+    pipeline::object::CollisionGroups,
+    query::{Ray, RayCast},
+};
+
+/// Line of sight queries on borrowed storages. This is useful for systems that already
+/// fetch the collision world or the colliders mutably and cannot use `Vision`.
+pub struct LineOfSight<'a> {
+    obstacles: &'a ReadStorage<'a, Obstacle>,
+    colliders: &'a ReadStorage<'a, Collider>,
+    collision: &'a MyCollisionWorld,
+}
+
+impl<'a> LineOfSight<'a> {
+    pub fn new(
+        obstacles: &'a ReadStorage<'a, Obstacle>,
+        colliders: &'a ReadStorage<'a, Collider>,
+        collision: &'a MyCollisionWorld,
+    ) -> Self {
+        Self {
+            obstacles,
+            colliders,
+            collision,
+        }
+    }
+
+    /// Return true if nothing blocks the segment between `from` and `to`.
+    ///
+    /// Obstacles that have a collider are tested against the exact shape of the collider,
+    /// the others against their AABB.
+    pub fn line_of_sight(&self, from: Point2<f32>, to: Point2<f32>) -> bool {
+        let ray = Ray::new(from, to - from);
+
+        // The broad phase only returns the walls on the way of the ray.
+        let mut groups = CollisionGroups::new();
+        groups.set_whitelist(&[ColliderObjectType::Wall.get_collider_group()]);
+        let blocked = self
+            .collision
+            .world
+            .interferences_with_ray(&ray, &groups)
+            .any(|(_, obj, intersection)| {
+                let is_obstacle = obj
+                    .data()
+                    .entity()
+                    .map_or(false, |e| self.obstacles.contains(e));
+                is_obstacle && is_blocking(Some(intersection.toi))
+            });
+        if blocked {
+            return false;
+        }
+
+        // Obstacles without collider are not in the collision world.
+        let identity = Isometry::identity();
+        !(self.obstacles, !self.colliders)
+            .join()
+            .any(|(obstacle, _)| is_blocking(obstacle.aabb.toi_with_ray(&identity, &ray, true)))
+    }
+
+    /// Return true if `to` is in the field of view of an observer at `from` looking in the
+    /// `facing` direction and nothing blocks the vision.
+    ///
+    /// `fov` is the full angle of the vision cone in radians.
+    pub fn can_see(
+        &self,
+        from: Point2<f32>,
+        facing: Vector2<f32>,
+        view_distance: f32,
+        fov: f32,
+        to: Point2<f32>,
+    ) -> bool {
+        in_view_cone(from, facing, view_distance, fov, to) && self.line_of_sight(from, to)
+    }
+}
+
+#[derive(SystemData)]
+pub struct Vision<'s> {
+    entities: Entities<'s>,
+    obstacles: ReadStorage<'s, Obstacle>,
+    colliders: ReadStorage<'s, Collider>,
+    transforms: ReadStorage<'s, Transform>,
+    collision: Read<'s, MyCollisionWorld>,
+}
+
+impl<'s> Vision<'s> {
+    pub fn query(&self) -> LineOfSight {
+        LineOfSight::new(&self.obstacles, &self.colliders, &self.collision)
+    }
+
+    /// See `LineOfSight::line_of_sight`
+    pub fn line_of_sight(&self, from: Point2<f32>, to: Point2<f32>) -> bool {
+        self.query().line_of_sight(from, to)
+    }
+
+    /// See `LineOfSight::can_see`
+    pub fn can_see(
+        &self,
+        from: Point2<f32>,
+        facing: Vector2<f32>,
+        view_distance: f32,
+        fov: f32,
+        to: Point2<f32>,
+    ) -> bool {
+        self.query().can_see(from, facing, view_distance, fov, to)
+    }
+
+    /// All the entities with a collider that can be seen from `from`. Obstacles are not
+    /// returned.
+    pub fn visible_entities(
+        &self,
+        from: Point2<f32>,
+        facing: Vector2<f32>,
+        radius: f32,
+        fov: f32,
+    ) -> Vec<Entity> {
+        let query = self.query();
+        (&self.entities, &self.colliders, &self.transforms, !&self.obstacles)
+            .join()
+            .filter_map(|(entity, _, transform, _)| {
+                let position = Point2::from(transform.translation().xy());
+                if query.can_see(from, facing, radius, fov, position) {
+                    Some(entity)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// The ray goes from the observer to the target so anything before the end of the
+/// ray is in the way.
+fn is_blocking(toi: Option<f32>) -> bool {
+    toi.map(|toi| toi < 1.0).unwrap_or(false)
+}
+
+/// Check the distance and the angle between the observer and the target. A zero facing
+/// vector means that the observer looks in all directions.
+pub fn in_view_cone(
+    from: Point2<f32>,
+    facing: Vector2<f32>,
+    view_distance: f32,
+    fov: f32,
+    to: Point2<f32>,
+) -> bool {
+    let direction = to - from;
+    let distance = direction.norm();
+    if distance > view_distance {
+        return false;
+    }
+
+    if distance <= std::f32::EPSILON || facing.norm() <= std::f32::EPSILON {
+        return true;
+    }
+
+    facing.angle(&direction) <= fov / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::{Builder, WorldExt};
+    use ncollide2d::bounding_volume;
+    use std::f32::consts::FRAC_PI_2;
+
+    /// World with a square wall between (0, 0) and (10, 10) and a triangle wall with
+    /// the corners (20, 0), (30, 0) and (20, 10).
+    fn setup() -> World {
+        let mut world = World::new();
+        world.register::<Obstacle>();
+        world.register::<Collider>();
+        world.register::<Transform>();
+        world.insert(MyCollisionWorld::default());
+
+        add_wall(&mut world, |collision| {
+            Collider::new_rect(
+                Vector2::new(5.0, 5.0),
+                10.0,
+                10.0,
+                &mut collision.world,
+                ColliderObjectType::Wall,
+                None,
+                None,
+            )
+        });
+        add_wall(&mut world, |collision| {
+            Collider::new_polygon(
+                Vector2::new(20.0, 0.0),
+                &[
+                    Point2::new(0.0, 0.0),
+                    Point2::new(10.0, 0.0),
+                    Point2::new(0.0, 10.0),
+                ],
+                &mut collision.world,
+                ColliderObjectType::Wall,
+                None,
+                None,
+            )
+            .unwrap()
+        });
+
+        // Fill the broad phase.
+        world.write_resource::<MyCollisionWorld>().world.update();
+        world
+    }
+
+    fn add_wall(world: &mut World, create: impl FnOnce(&mut MyCollisionWorld) -> Collider) {
+        let collider = create(&mut world.write_resource::<MyCollisionWorld>());
+        let aabb = {
+            let collision = world.read_resource::<MyCollisionWorld>();
+            let obj = collision.world.collision_object(collider.handle).unwrap();
+            bounding_volume::aabb(obj.shape().as_ref(), obj.position())
+        };
+        let entity = world
+            .create_entity()
+            .with(Obstacle { aabb })
+            .with(collider)
+            .build();
+        collider.set_entity(&mut world.write_resource::<MyCollisionWorld>().world, entity);
+    }
+
+    fn line_of_sight(world: &World, from: (f32, f32), to: (f32, f32)) -> bool {
+        world
+            .system_data::<Vision>()
+            .line_of_sight(Point2::new(from.0, from.1), Point2::new(to.0, to.1))
+    }
+
+    #[test]
+    fn visible_when_nothing_in_the_way() {
+        let world = setup();
+        assert!(line_of_sight(&world, (-10.0, 20.0), (40.0, 20.0)));
+        assert!(line_of_sight(&world, (15.0, -10.0), (15.0, 30.0)));
+    }
+
+    #[test]
+    fn blocked_by_rectangle() {
+        let world = setup();
+        assert!(!line_of_sight(&world, (-10.0, 5.0), (15.0, 5.0)));
+        assert!(!line_of_sight(&world, (5.0, -10.0), (5.0, 20.0)));
+    }
+
+    #[test]
+    fn blocked_by_polygon() {
+        let world = setup();
+        assert!(!line_of_sight(&world, (15.0, 2.0), (35.0, 2.0)));
+        // Above the slope of the triangle, but inside its AABB.
+        assert!(line_of_sight(&world, (26.0, 9.0), (29.0, 6.0)));
+    }
+
+    #[test]
+    fn target_before_the_wall_is_visible() {
+        let world = setup();
+        assert!(line_of_sight(&world, (-10.0, 5.0), (-1.0, 5.0)));
+    }
+
+    #[test]
+    fn ray_grazing_a_corner() {
+        let world = setup();
+        // Just outside the top right corner of the square.
+        assert!(line_of_sight(&world, (0.1, 20.1), (15.1, 5.1)));
+        // Just inside of it.
+        assert!(!line_of_sight(&world, (-0.1, 19.9), (14.9, 4.9)));
+    }
+
+    #[test]
+    fn can_see_checks_range_and_cone() {
+        let world = setup();
+        let vision = world.system_data::<Vision>();
+        let from = Point2::new(-10.0, 20.0);
+        let facing = Vector2::new(1.0, 0.0);
+
+        assert!(vision.can_see(from, facing, 100.0, FRAC_PI_2, Point2::new(40.0, 20.0)));
+        // Out of range.
+        assert!(!vision.can_see(from, facing, 10.0, FRAC_PI_2, Point2::new(40.0, 20.0)));
+        // Behind the observer.
+        assert!(!vision.can_see(from, facing, 100.0, FRAC_PI_2, Point2::new(-40.0, 20.0)));
+        // In the cone but behind the square.
+        assert!(!vision.can_see(
+            Point2::new(-10.0, 5.0),
+            facing,
+            100.0,
+            FRAC_PI_2,
+            Point2::new(15.0, 5.0)
+        ));
+    }
+
+    #[test]
+    fn view_cone() {
+        let from = Point2::new(0.0, 0.0);
+        let facing = Vector2::new(0.0, 1.0);
+        assert!(in_view_cone(from, facing, 10.0, FRAC_PI_2, Point2::new(1.0, 5.0)));
+        assert!(!in_view_cone(from, facing, 10.0, FRAC_PI_2, Point2::new(5.0, 1.0)));
+        assert!(!in_view_cone(from, facing, 10.0, FRAC_PI_2, Point2::new(0.0, 11.0)));
+        // No facing direction means that the observer looks everywhere.
+        assert!(in_view_cone(from, Vector2::zeros(), 10.0, 0.0, Point2::new(0.0, -5.0)));
+    }
+
+    #[test]
+    fn blocking_toi() {
+        assert!(is_blocking(Some(0.5)));
+        assert!(!is_blocking(Some(1.5)));
+        assert!(!is_blocking(None));
+    }
+}